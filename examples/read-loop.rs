@@ -11,7 +11,7 @@ use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use ezo_ph::{
-    command::{Command, Reading, Sleep}, response::SensorReading,
+    command::{Command, Reading, Sleep}, response::SensorReading, retry::RetryPolicy,
 };
 use failure::{Error, ResultExt};
 use i2cdev::linux::LinuxI2CDevice;
@@ -25,12 +25,19 @@ fn run() -> Result<(), Error> {
     let mut dev =
         LinuxI2CDevice::new(&device_path, EZO_SENSOR_ADDR).context("Could not open I2C device")?;
 
-    loop {
-        let ph_value = Reading.run(&mut dev)?;
-
-        let _out = _print_response(ph_value)?;
+    let policy = RetryPolicy::default();
 
-        let _sleep = Sleep.run(&mut dev)?;
+    loop {
+        // Transient I2C errors are retried, and logged when the policy
+        // gives up, so that the loop keeps going.
+        match policy.run(|| Reading.run(&mut dev)) {
+            Ok(ph_value) => _print_response(ph_value)?,
+            Err(e) => eprintln!("reading failed: {}", e),
+        }
+
+        if let Err(e) = policy.run(|| Sleep.run(&mut dev)) {
+            eprintln!("sleep failed: {}", e);
+        }
 
         // Ideally, every 10 seconds, fine-tune this to your hardware.
         thread::sleep(Duration::from_millis(9_100));
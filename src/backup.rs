@@ -0,0 +1,344 @@
+//! Backup of the calibration of the PH EZO Chip, to restore it later on
+//! the same chip, or on a replacement.
+//!
+//! The calibration is read with `EXPORT`, as strings that the chip alone
+//! understands, and written back with `IMPORT`. The record also keeps the
+//! device information, calibration status and probe slope, which are
+//! checked against the chip after restoring it.
+use std::fmt;
+
+use super::command::{Export, ExportInfo};
+use super::response::{CalibrationStatus, Exported, ProbeSlope};
+use super::sensor::PhSensor;
+use super::transport::EzoTransport;
+use super::{ErrorKind, EzoError};
+
+use chrono::{DateTime, Utc};
+
+use failure::Fail;
+
+/// Largest difference, in percent or mV, between the slope in the record
+/// and the slope after restoring it.
+pub const SLOPE_TOLERANCE: f64 = 0.1;
+
+/// A calibration backup.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationRecord {
+    /// When the calibration was exported.
+    pub timestamp: DateTime<Utc>,
+    /// Device type, as reported by the `I` command.
+    pub device: String,
+    /// Firmware version, as reported by the `I` command.
+    pub firmware: String,
+    /// Calibration status when exported.
+    pub calibration: CalibrationStatus,
+    /// Probe slope when exported.
+    pub slope: ProbeSlope,
+    /// Strings sent by the device on `EXPORT`.
+    pub data: Vec<String>,
+}
+
+impl CalibrationRecord {
+    /// Exports the calibration of the sensor.
+    pub fn export<T: EzoTransport>(
+        sensor: &mut PhSensor<T>,
+    ) -> Result<CalibrationRecord, EzoError> {
+        let info = sensor.info()?;
+        let calibration = sensor.calibration_status()?;
+        let slope = sensor.slope()?;
+        let expected = sensor.run(&ExportInfo)?;
+        let mut data = Vec::new();
+        loop {
+            match sensor.run(&Export)? {
+                Exported::ExportString(string) => data.push(string),
+                Exported::Done => break,
+            }
+            if data.len() > expected.lines as usize {
+                return Err(ErrorKind::MalformedResponse)?;
+            }
+        }
+        let bytes: usize = data.iter().map(|s| s.len()).sum();
+        if data.len() != expected.lines as usize || bytes != expected.total_bytes as usize {
+            return Err(ErrorKind::MalformedResponse)?;
+        }
+        Ok(CalibrationRecord {
+            timestamp: Utc::now(),
+            device: info.device,
+            firmware: info.firmware,
+            calibration,
+            slope,
+            data,
+        })
+    }
+
+    /// Imports the calibration onto the sensor, which must be of the same
+    /// device type, and checks that its calibration status and slope then
+    /// match the record. The import is recorded in the sensor's history,
+    /// if it has one.
+    pub fn restore<T: EzoTransport>(&self, sensor: &mut PhSensor<T>) -> Result<(), BackupError> {
+        let info = sensor.info()?;
+        if info.device != self.device {
+            return Err(BackupError::WrongDevice {
+                expected: self.device.clone(),
+                found: info.device,
+            });
+        }
+        sensor.import_calibration(&self.data)?;
+        let calibration = sensor.calibration_status()?;
+        if calibration != self.calibration {
+            return Err(BackupError::CalibrationMismatch {
+                expected: self.calibration,
+                found: calibration,
+            });
+        }
+        let slope = sensor.slope()?;
+        if !slopes_match(&slope, &self.slope) {
+            return Err(BackupError::SlopeMismatch {
+                expected: self.slope,
+                found: slope,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn slopes_match(a: &ProbeSlope, b: &ProbeSlope) -> bool {
+    let offsets_match = match (a.zero_offset_mv, b.zero_offset_mv) {
+        (Some(a), Some(b)) => (a - b).abs() <= SLOPE_TOLERANCE,
+        _ => true,
+    };
+    (a.acid_end - b.acid_end).abs() <= SLOPE_TOLERANCE
+        && (a.base_end - b.base_end).abs() <= SLOPE_TOLERANCE
+        && offsets_match
+}
+
+/// Error returned when restoring a `CalibrationRecord`.
+#[derive(Debug)]
+pub enum BackupError {
+    /// The record was exported from another type of device.
+    WrongDevice { expected: String, found: String },
+    /// The calibration status after restoring does not match the record.
+    CalibrationMismatch {
+        expected: CalibrationStatus,
+        found: CalibrationStatus,
+    },
+    /// The probe slope after restoring does not match the record.
+    SlopeMismatch {
+        expected: ProbeSlope,
+        found: ProbeSlope,
+    },
+    /// Communicating with the device failed.
+    Device(EzoError),
+}
+
+impl From<EzoError> for BackupError {
+    fn from(error: EzoError) -> BackupError {
+        BackupError::Device(error)
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackupError::WrongDevice {
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "calibration of a {} device cannot be restored on a {} device",
+                expected, found
+            ),
+            BackupError::CalibrationMismatch { expected, found } => write!(
+                f,
+                "restored calibration is {}, expected {}",
+                found, expected
+            ),
+            BackupError::SlopeMismatch { expected, found } => {
+                write!(f, "restored slope is {}, expected {}", found, expected)
+            }
+            BackupError::Device(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Fail for BackupError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            BackupError::Device(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use history::MemoryHistory;
+    use mock::MockDevice;
+    use sensor::DEFAULT_ADDRESS;
+    use simulator::{ProbeModel, Simulator};
+
+    use serde_json;
+
+    fn calibrated_sensor() -> PhSensor<Simulator> {
+        let probe = ProbeModel {
+            offset_mv: 12.0,
+            acid_slope: 96.0,
+            base_slope: 93.0,
+            ..ProbeModel::default()
+        };
+        let mut sensor = PhSensor::new(Simulator::new(probe), DEFAULT_ADDRESS);
+        sensor.calibrate_mid(7.0).unwrap();
+        sensor.transport_mut().set_solution_ph(4.0);
+        sensor.calibrate_low(4.0).unwrap();
+        sensor.transport_mut().set_solution_ph(10.0);
+        sensor.calibrate_high(10.0).unwrap();
+        sensor
+    }
+
+    #[test]
+    fn restores_calibration_on_replacement_chip() {
+        let mut sensor = calibrated_sensor();
+        let record = CalibrationRecord::export(&mut sensor).unwrap();
+        assert_eq!(record.device, "pH");
+        assert_eq!(record.calibration, CalibrationStatus::ThreePoint);
+        assert_eq!(record.data.len(), 9);
+
+        let json = serde_json::to_string(&record).unwrap();
+        let record: CalibrationRecord = serde_json::from_str(&json).unwrap();
+
+        let mut replacement = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        record.restore(&mut replacement).unwrap();
+        assert_eq!(replacement.slope().unwrap(), sensor.slope().unwrap());
+        assert_eq!(
+            replacement.last_calibration(),
+            Some(CalibrationStatus::ThreePoint)
+        );
+    }
+
+    #[test]
+    fn records_restores_in_history() {
+        let mut sensor = calibrated_sensor();
+        let record = CalibrationRecord::export(&mut sensor).unwrap();
+
+        let mut replacement = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        replacement.set_history(Some(Box::new(MemoryHistory::new())));
+        record.restore(&mut replacement).unwrap();
+        let entries = replacement.history().unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].imported);
+        assert_eq!(entries[0].status, CalibrationStatus::ThreePoint);
+        assert_eq!(entries[0].slope_after, record.slope);
+    }
+
+    #[test]
+    fn restoring_clears_points_missing_from_record() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.calibrate_mid(7.0).unwrap();
+        let record = CalibrationRecord::export(&mut sensor).unwrap();
+        assert_eq!(
+            record.data,
+            vec!["MP7.000", "MV0.0000", "MT25.000", "LP-", "HP-"]
+        );
+
+        let mut other = calibrated_sensor();
+        record.restore(&mut other).unwrap();
+        assert_eq!(other.transport().calibration_points(), 1);
+    }
+
+    #[test]
+    fn refuses_other_devices() {
+        let mut sensor = calibrated_sensor();
+        let mut record = CalibrationRecord::export(&mut sensor).unwrap();
+        record.device = "EC".to_string();
+        let err = record.restore(&mut sensor).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "calibration of a EC device cannot be restored on a pH device"
+        );
+    }
+
+    #[test]
+    fn detects_mismatch_after_restoring() {
+        let mut sensor = calibrated_sensor();
+        let record = CalibrationRecord::export(&mut sensor).unwrap();
+
+        let mut dev = MockDevice::new();
+        dev.queue_data("?I,pH,2.10");
+        for _ in &record.data {
+            dev.queue_ack();
+        }
+        dev.queue_data("?CAL,1");
+        let mut other = PhSensor::new(dev, DEFAULT_ADDRESS);
+        match record.restore(&mut other).unwrap_err() {
+            BackupError::CalibrationMismatch { expected, found } => {
+                assert_eq!(expected, CalibrationStatus::ThreePoint);
+                assert_eq!(found, CalibrationStatus::OnePoint);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(other.transport().commands()[1], "IMPORT,MP7.000");
+    }
+
+    /// Calibration strings in the chip's own format: ten strings of twelve
+    /// hexadecimal digits, as announced by `?EXPORT,10,120`.
+    const EXPORTED: [&str; 10] = [
+        "59006F007500",
+        "2000610072FF",
+        "6500200061FE",
+        "20006300FF3C",
+        "6C0065007600",
+        "6500720020C8",
+        "6700750079D2",
+        "0A00210000F4",
+        "3F0000E00040",
+        "0000A0410000",
+    ];
+
+    #[test]
+    fn exports_and_restores_the_chip_format() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("?I,pH,2.10")
+            .queue_data("?CAL,3")
+            .queue_data("?SLOPE,99.7,100.3,-0.89")
+            .queue_data("?EXPORT,10,120");
+        for string in EXPORTED.iter() {
+            dev.queue_data(string);
+        }
+        dev.queue_data("*DONE");
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        let record = CalibrationRecord::export(&mut sensor).unwrap();
+        assert_eq!(record.data, EXPORTED);
+        assert_eq!(record.calibration, CalibrationStatus::ThreePoint);
+        assert_eq!(sensor.transport().commands().len(), 15);
+
+        let mut dev = MockDevice::new();
+        dev.queue_data("?I,pH,2.10");
+        for _ in EXPORTED.iter() {
+            dev.queue_ack();
+        }
+        dev.queue_data("?CAL,3")
+            .queue_data("?SLOPE,99.7,100.3,-0.89");
+        let mut replacement = PhSensor::new(dev, DEFAULT_ADDRESS);
+        record.restore(&mut replacement).unwrap();
+        let commands = replacement.transport().commands();
+        assert_eq!(commands[1], "IMPORT,59006F007500");
+        assert_eq!(commands[10], "IMPORT,0000A0410000");
+        assert_eq!(commands[11..], ["CAL,?", "SLOPE,?"]);
+    }
+
+    #[test]
+    fn export_checks_line_count() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("?I,pH,2.10")
+            .queue_data("?CAL,1")
+            .queue_data("?SLOPE,100.0,100.0")
+            .queue_data("?EXPORT,1,7")
+            .queue_data("MP7.000")
+            .queue_data("MV0.0000")
+            .queue_data("*DONE");
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        let err = CalibrationRecord::export(&mut sensor).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MalformedResponse);
+    }
+}
@@ -0,0 +1,91 @@
+//! Helpers shared by the command-line tools.
+//!
+//! Included in each of them with `mod common;`, so that they are not part of
+//! the library. Not every tool uses every helper.
+#![allow(dead_code)]
+use std::num::ParseIntError;
+
+use ezo_ph::command::NameState;
+use ezo_ph::sensor::PhSensor;
+use ezo_ph::transport::EzoTransport;
+use ezo_ph::EzoError;
+
+use failure::Error;
+
+/// Parses a decimal number, or a hexadecimal one prefixed with `0x`.
+pub fn parse_number(s: &str) -> Result<u16, ParseIntError> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u16::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse()
+    }
+}
+
+/// The error and its causes, separated by colons.
+pub fn error_chain(error: &Error) -> String {
+    let causes: Vec<String> = error.iter_chain().map(|c| c.to_string()).collect();
+    causes.join(": ")
+}
+
+/// Whether a device name can be used in metric labels and MQTT topics: it
+/// is not empty, and has no `/`, `+` or `#`.
+pub fn is_device_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(&['/', '+', '#'][..])
+}
+
+/// The name the device is known by: its own name, or its address in hex
+/// when the name is not set, or cannot be used.
+pub fn device_name<T: EzoTransport>(sensor: &mut PhSensor<T>) -> Result<String, EzoError> {
+    let name = sensor.run(&NameState)?.0;
+    if is_device_name(&name) {
+        Ok(name)
+    } else {
+        Ok(format!("0x{:02x}", sensor.address()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ezo_ph::mock::MockDevice;
+    use ezo_ph::sensor::DEFAULT_ADDRESS;
+
+    use failure::{err_msg, ResultExt};
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("0x63"), Ok(DEFAULT_ADDRESS));
+        assert_eq!(parse_number("0X63"), Ok(DEFAULT_ADDRESS));
+        assert_eq!(parse_number("99"), Ok(DEFAULT_ADDRESS));
+        assert!(parse_number("0xZZ").is_err());
+    }
+
+    #[test]
+    fn joins_the_causes() {
+        let error: Error = Err::<(), _>(err_msg("no such file"))
+            .context("could not open /dev/i2c-1")
+            .unwrap_err()
+            .into();
+        assert_eq!(
+            error_chain(&error),
+            "could not open /dev/i2c-1: no such file"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_address() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("?NAME,tank-1")
+            .queue_data("?NAME,")
+            .queue_data("?NAME,tank/1");
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        assert_eq!(device_name(&mut sensor).unwrap(), "tank-1");
+        assert_eq!(device_name(&mut sensor).unwrap(), "0x63");
+        assert_eq!(device_name(&mut sensor).unwrap(), "0x63");
+
+        assert!(is_device_name("tank-1"));
+        assert!(!is_device_name(""));
+        assert!(!is_device_name("tank+1"));
+        assert!(!is_device_name("#"));
+    }
+}
@@ -0,0 +1,141 @@
+//! Prometheus exporter for PH EZO Chips.
+//!
+//! Serves the metrics of the devices at `http://<listen>/metrics`. The
+//! devices are read every `--interval` seconds, and scrapes get the last
+//! readings.
+#[macro_use]
+extern crate clap;
+extern crate ezo_ph;
+extern crate failure;
+
+mod common;
+
+use std::num::ParseIntError;
+use std::process;
+use std::time::Duration;
+
+use clap::{App, Arg};
+use ezo_ph::metrics::Exporter;
+use ezo_ph::sensor::{PhSensor, DEFAULT_ADDRESS};
+use failure::{Error, ResultExt};
+
+use common::{device_name, error_chain, parse_number};
+
+/// Default address to listen on.
+const LISTEN: &str = "0.0.0.0:9721";
+
+/// Parses `ADDRESS[=NAME]`.
+fn parse_device(s: &str) -> Result<(u16, Option<String>), ParseIntError> {
+    let mut split = s.splitn(2, '=');
+    let address = parse_number(split.next().unwrap())?;
+    Ok((address, split.next().map(|name| name.to_string())))
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ezo-ph-exporter")
+        .version(crate_version!())
+        .about("Serves the metrics of PH EZO Chips to Prometheus.")
+        .arg(
+            Arg::with_name("bus")
+                .long("bus")
+                .short("b")
+                .value_name("BUS")
+                .default_value("1")
+                .validator(|s| s.parse::<u8>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Number of the I2C bus, as in /dev/i2c-<BUS>"),
+        )
+        .arg(
+            Arg::with_name("device")
+                .long("device")
+                .short("d")
+                .value_name("ADDRESS[=NAME]")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| parse_device(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .help(
+                    "Address of a chip, and its name in the metrics. Without a name, the \
+                     chip's own name is used, or its address if it has none [default: 0x63]",
+                ),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .short("i")
+                .value_name("SECONDS")
+                .default_value("15")
+                .validator(|s| match s.parse::<u64>() {
+                    Ok(0) => Err("the interval must be at least one second".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+                .help("Seconds between readings of the chips"),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .short("l")
+                .value_name("ADDR:PORT")
+                .default_value(LISTEN)
+                .help("Address to serve the metrics on"),
+        )
+}
+
+fn run() -> Result<(), Error> {
+    let matches = app().get_matches();
+    let bus: u8 = matches.value_of("bus").unwrap().parse()?;
+    let interval = Duration::from_secs(matches.value_of("interval").unwrap().parse()?);
+    let devices = match matches.values_of("device") {
+        Some(values) => values.map(parse_device).collect::<Result<Vec<_>, _>>()?,
+        None => vec![(DEFAULT_ADDRESS, None)],
+    };
+
+    let mut exporter = Exporter::new();
+    for (address, name) in devices {
+        let mut sensor = PhSensor::open(bus, address)
+            .with_context(|_| format!("could not open /dev/i2c-{}", bus))?;
+        let name = match name {
+            Some(name) => name,
+            None => device_name(&mut sensor)
+                .with_context(|_| format!("could not query the name at 0x{:02x}", address))?,
+        };
+        exporter.add(sensor, bus, &name);
+    }
+
+    let listen = matches.value_of("listen").unwrap();
+    eprintln!("serving metrics on http://{}/metrics", listen);
+    exporter
+        .serve(listen, interval)
+        .with_context(|_| format!("could not serve on {}", listen))?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", error_chain(&e));
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_devices() {
+        assert_eq!(parse_device("0x63"), Ok((0x63, None)));
+        assert_eq!(
+            parse_device("100=tank-2"),
+            Ok((100, Some("tank-2".to_string())))
+        );
+        assert!(parse_device("tank=0x63").is_err());
+
+        let matches = app()
+            .get_matches_from_safe(vec!["ezo-ph-exporter", "-d", "0x63", "-d", "0x64=b"])
+            .unwrap();
+        assert_eq!(matches.values_of("device").unwrap().count(), 2);
+        assert_eq!(matches.value_of("listen"), Some(LISTEN));
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph-exporter", "-i", "0"])
+            .is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Configuration of the logger, read from a TOML file.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ezo_ph::sensor::DEFAULT_ADDRESS;
+use failure::{err_msg, Error, ResultExt};
+use toml;
+
+/// Configuration of the logger.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// I2C bus of the devices, unless they set their own.
+    #[serde(default = "default_bus")]
+    pub bus: u8,
+    /// Seconds between readings.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Whether the chips are put to sleep between readings.
+    #[serde(default)]
+    pub sleep: bool,
+    /// Temperature to compensate the readings for.
+    #[serde(default)]
+    pub temperature: Option<TemperatureSource>,
+    #[serde(rename = "device")]
+    pub devices: Vec<DeviceConfig>,
+    /// Where the readings go, the standard output if none are given.
+    #[serde(rename = "output", default = "default_outputs")]
+    pub outputs: Vec<OutputConfig>,
+}
+
+fn default_bus() -> u8 {
+    1
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_address() -> u16 {
+    DEFAULT_ADDRESS
+}
+
+fn default_outputs() -> Vec<OutputConfig> {
+    vec![OutputConfig::Stdout {
+        format: Format::Text,
+    }]
+}
+
+impl Config {
+    /// Reads the configuration from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|_| format!("could not read {}", path.display()))?;
+        let config: Config = contents
+            .parse::<Config>()
+            .with_context(|_| format!("invalid configuration in {}", path.display()))?;
+        Ok(config)
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Config, Error> {
+        let config: Config = toml::from_str(s)?;
+        if config.devices.is_empty() {
+            return Err(err_msg("no devices are configured"));
+        }
+        if config.interval == 0 {
+            return Err(err_msg("the interval must be at least one second"));
+        }
+        Ok(config)
+    }
+}
+
+/// A device to log.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// Name of the device in the log.
+    pub name: String,
+    #[serde(default = "default_address")]
+    pub address: u16,
+    /// I2C bus of the device, if not the default one.
+    #[serde(default)]
+    pub bus: Option<u8>,
+}
+
+/// Where the compensation temperature comes from.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureSource {
+    /// A fixed temperature, in °C.
+    Fixed(f64),
+    /// A file holding the temperature, either in °C, or in thousandths of
+    /// °C after `t=`, as the 1-Wire `w1_slave` files do. A `w1_slave`
+    /// reading that failed its CRC check is an error.
+    File(PathBuf),
+}
+
+impl TemperatureSource {
+    /// The current temperature, in °C.
+    pub fn read(&self) -> Result<f64, Error> {
+        match *self {
+            TemperatureSource::Fixed(temperature) => Ok(temperature),
+            TemperatureSource::File(ref path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|_| format!("could not read {}", path.display()))?;
+                let temperature = parse_temperature(&contents)
+                    .ok_or_else(|| err_msg(format!("no temperature in {}", path.display())))?;
+                Ok(temperature)
+            }
+        }
+    }
+}
+
+/// Parses a temperature in °C, or the contents of a `w1_slave` file, whose
+/// temperature is only valid if its first line ends with `YES`.
+fn parse_temperature(contents: &str) -> Option<f64> {
+    let pos = match contents.rfind("t=") {
+        Some(pos) => pos,
+        None => return contents.trim().parse().ok(),
+    };
+    match contents.lines().next() {
+        Some(line) if line.trim_end().ends_with("YES") => contents[pos + 2..]
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|millis| millis / 1_000.0),
+        _ => None,
+    }
+}
+
+/// Format of the readings in an output.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One line per reading, for people.
+    Text,
+    /// One JSON object per line.
+    Json,
+    /// Comma-separated values, after a header line.
+    Csv,
+}
+
+// Written out, as `#[default]` variants need a newer compiler.
+#[allow(clippy::derivable_impls)]
+impl Default for Format {
+    fn default() -> Format {
+        Format::Text
+    }
+}
+
+/// An output of the readings.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum OutputConfig {
+    /// The standard output.
+    Stdout {
+        #[serde(default)]
+        format: Format,
+    },
+    /// A file, which is appended to.
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: Format,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn parses_example_configuration() {
+        let config: Config = include_str!("../../../examples/ezo-ph-logger.toml")
+            .parse()
+            .unwrap();
+        assert_eq!(config.interval, 10);
+        assert!(config.sleep);
+        assert_eq!(config.temperature, Some(TemperatureSource::Fixed(25.0)));
+        assert_eq!(
+            config.devices[1],
+            DeviceConfig {
+                name: "tank-2".to_string(),
+                address: 0x64,
+                bus: Some(3),
+            }
+        );
+        assert_eq!(
+            config.outputs[1],
+            OutputConfig::File {
+                path: PathBuf::from("/var/log/ezo-ph.jsonl"),
+                format: Format::Json,
+            }
+        );
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let config: Config = "[[device]]\nname = \"tank\"\n".parse().unwrap();
+        assert_eq!(config.bus, 1);
+        assert_eq!(config.interval, 10);
+        assert!(!config.sleep);
+        assert_eq!(config.temperature, None);
+        assert_eq!(config.devices[0].address, DEFAULT_ADDRESS);
+        assert_eq!(config.outputs, default_outputs());
+    }
+
+    #[test]
+    fn rejects_invalid_configuration() {
+        assert!("bus = 1\ndevice = []\n".parse::<Config>().is_err());
+        assert!("interval = 0\n[[device]]\nname = \"tank\"\n"
+            .parse::<Config>()
+            .is_err());
+        assert!("[[device]]\nname = \"tank\"\nadress = 99\n"
+            .parse::<Config>()
+            .is_err());
+        assert!(
+            "[[device]]\nname = \"tank\"\n[[output]]\ntype = \"syslog\"\n"
+                .parse::<Config>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reads_temperature_files() {
+        assert_eq!(parse_temperature("21.5\n"), Some(21.5));
+        let w1_slave = "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n\
+                        72 01 4b 46 7f ff 0e 10 57 t=23125\n";
+        assert_eq!(parse_temperature(w1_slave), Some(23.125));
+        let bad_crc = "72 01 4b 46 7f ff 0e 10 57 : crc=a1 NO\n\
+                       72 01 4b 46 7f ff 0e 10 57 t=85000\n";
+        assert_eq!(parse_temperature(bad_crc), None);
+        assert_eq!(parse_temperature("n/a"), None);
+
+        let path = env::temp_dir().join(format!("ezo-ph-temperature-{}", process::id()));
+        fs::write(&path, "19.25").unwrap();
+        let source = TemperatureSource::File(path.clone());
+        assert_eq!(source.read().unwrap(), 19.25);
+        fs::remove_file(&path).unwrap();
+        assert!(source.read().is_err());
+    }
+}
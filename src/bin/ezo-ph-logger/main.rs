@@ -0,0 +1,287 @@
+//! Daemon that logs pH readings on a schedule.
+//!
+//! Run as `ezo-ph-logger <config.toml>`; see `examples/ezo-ph-logger.toml`.
+//! The devices are read every `interval` seconds until the process gets
+//! SIGTERM or SIGINT, which stop it once the current readings are written.
+//! Device errors are logged, and do not keep the other devices, or the
+//! next readings, from being taken.
+extern crate chrono;
+extern crate ezo_ph;
+extern crate failure;
+extern crate i2cdev;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate signal_hook;
+extern crate toml;
+
+#[path = "../common/mod.rs"]
+mod common;
+mod config;
+mod output;
+
+use std::cmp;
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use ezo_ph::command::{ReadingWithTemperature, ScaledReading};
+use ezo_ph::response::PhScale;
+use ezo_ph::retry::RetryPolicy;
+use ezo_ph::sensor::PhSensor;
+use ezo_ph::transport::EzoTransport;
+use failure::{err_msg, Error, ResultExt};
+use i2cdev::linux::LinuxI2CDevice;
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use common::error_chain;
+use config::{Config, TemperatureSource};
+use output::{Output, Sample};
+
+/// How often a stop is checked for, while waiting for the next readings.
+const STOP_CHECK_MILLIS: u64 = 100;
+
+/// Logs a message to the standard error, with the time.
+fn log(message: &str) {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    eprintln!("{} {}", now, message);
+}
+
+/// A device being logged.
+struct Probe<T: EzoTransport> {
+    name: String,
+    bus: u8,
+    sensor: PhSensor<T>,
+}
+
+/// The devices, and where their readings go.
+struct Logger<T: EzoTransport> {
+    probes: Vec<Probe<T>>,
+    temperature: Option<TemperatureSource>,
+    sleep: bool,
+    policy: RetryPolicy,
+    outputs: Vec<Output>,
+}
+
+impl<T: EzoTransport> Logger<T> {
+    /// Reads every device once, and writes the readings to the outputs.
+    /// Errors are logged, and do not keep the other devices from being
+    /// read. Readings are compensated for the temperature, if it is known.
+    fn acquire(&mut self) {
+        let temperature = match self.temperature {
+            Some(ref source) => match source.read() {
+                Ok(temperature) => Some(temperature),
+                Err(e) => {
+                    log(&format!("temperature: {}", e));
+                    None
+                }
+            },
+            None => None,
+        };
+        for probe in &mut self.probes {
+            let result = {
+                // Readings on either scale are accepted, unless the scale
+                // of the device is known.
+                let scale = probe.sensor.last_scale().unwrap_or(PhScale::Extended);
+                let transport = probe.sensor.transport_mut();
+                match temperature {
+                    Some(t) => self.policy.run_on(&ReadingWithTemperature(t), transport),
+                    None => self.policy.run_on(&ScaledReading(scale), transport),
+                }
+            };
+            match result {
+                Ok(reading) => {
+                    let sample = Sample {
+                        timestamp: Utc::now(),
+                        device: probe.name.clone(),
+                        bus: probe.bus,
+                        address: probe.sensor.address(),
+                        ph: reading.0,
+                        temperature,
+                    };
+                    for output in &mut self.outputs {
+                        if let Err(e) = output.write(&sample) {
+                            log(&format!("could not write reading: {}", e));
+                        }
+                    }
+                }
+                Err(e) => log(&format!("{}: {}", probe.name, e)),
+            }
+            if self.sleep {
+                if let Err(e) = probe.sensor.sleep() {
+                    log(&format!("{}: could not sleep: {}", probe.name, e));
+                }
+            }
+        }
+    }
+}
+
+/// Opens the devices and outputs of the configuration.
+fn open(config: &Config) -> Result<Logger<LinuxI2CDevice>, Error> {
+    let mut probes = Vec::new();
+    for device in &config.devices {
+        let bus = device.bus.unwrap_or(config.bus);
+        let sensor = PhSensor::open(bus, device.address).with_context(|_| {
+            format!(
+                "could not open {} at 0x{:02x} on /dev/i2c-{}",
+                device.name, device.address, bus
+            )
+        })?;
+        probes.push(Probe {
+            name: device.name.clone(),
+            bus,
+            sensor,
+        });
+    }
+    let mut outputs = Vec::new();
+    for output in &config.outputs {
+        let opened =
+            Output::open(output).with_context(|_| format!("could not open {:?}", output))?;
+        outputs.push(opened);
+    }
+    Ok(Logger {
+        probes,
+        temperature: config.temperature.clone(),
+        sleep: config.sleep,
+        policy: RetryPolicy::default(),
+        outputs,
+    })
+}
+
+/// Sleeps until `deadline`, or until `stop` is set.
+fn wait_until(deadline: Instant, stop: &AtomicBool) {
+    let check = Duration::from_millis(STOP_CHECK_MILLIS);
+    while !stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(cmp::min(deadline - now, check));
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let path = env::args_os()
+        .nth(1)
+        .ok_or_else(|| err_msg("usage: ezo-ph-logger <config.toml>"))?;
+    let config = Config::load(&path)?;
+    let mut logger = open(&config)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&stop))?;
+
+    log(&format!(
+        "logging {} devices every {} s",
+        logger.probes.len(),
+        config.interval
+    ));
+    let interval = Duration::from_secs(config.interval);
+    while !stop.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        logger.acquire();
+        wait_until(started + interval, &stop);
+    }
+    log("stopped");
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        log(&format!("error: {}", error_chain(&e)));
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Format;
+    use ezo_ph::mock::MockDevice;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    /// A writer whose output can be checked after it is boxed.
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn probe(name: &str, dev: MockDevice) -> Probe<MockDevice> {
+        Probe {
+            name: name.to_string(),
+            bus: 1,
+            sensor: PhSensor::new(dev, 0x63),
+        }
+    }
+
+    #[test]
+    fn device_errors_do_not_stop_the_others() {
+        let mut failing = MockDevice::new();
+        failing.queue_code(2);
+        let mut working = MockDevice::new();
+        working.queue_data("7.012");
+
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let writer = Box::new(Shared(Rc::clone(&buffer)));
+        let mut logger = Logger {
+            probes: vec![probe("tank-1", failing), probe("tank-2", working)],
+            temperature: Some(TemperatureSource::Fixed(21.5)),
+            sleep: true,
+            policy: RetryPolicy::default(),
+            outputs: vec![Output::new(Format::Json, writer, false).unwrap()],
+        };
+        logger.acquire();
+
+        let written = String::from_utf8(buffer.borrow().clone()).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.contains("\"device\":\"tank-2\""));
+        assert!(written.contains("\"ph\":7.012,\"temperature\":21.5"));
+        for probe in &logger.probes {
+            assert_eq!(
+                probe.sensor.transport().commands(),
+                vec!["RT,21.500", "SLEEP"]
+            );
+        }
+    }
+
+    #[test]
+    fn logs_readings_on_the_extended_scale() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("-1.250");
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let writer = Box::new(Shared(Rc::clone(&buffer)));
+        let mut logger = Logger {
+            probes: vec![probe("tank", dev)],
+            temperature: None,
+            sleep: false,
+            policy: RetryPolicy::default(),
+            outputs: vec![Output::new(Format::Json, writer, false).unwrap()],
+        };
+        logger.acquire();
+
+        let written = String::from_utf8(buffer.borrow().clone()).unwrap();
+        assert!(written.contains("\"ph\":-1.25"));
+    }
+
+    #[test]
+    fn stop_ends_the_wait() {
+        let stop = AtomicBool::new(true);
+        let started = Instant::now();
+        wait_until(started + Duration::from_secs(60), &stop);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
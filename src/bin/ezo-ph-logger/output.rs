@@ -0,0 +1,160 @@
+//! Outputs the readings are written to.
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json;
+
+use config::{Format, OutputConfig};
+
+/// A reading of one of the devices.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    /// Name of the device.
+    pub device: String,
+    pub bus: u8,
+    pub address: u16,
+    pub ph: f64,
+    /// Temperature the reading was compensated for, if it was set.
+    pub temperature: Option<f64>,
+}
+
+const CSV_HEADER: &str = "timestamp,device,bus,address,ph,temperature";
+
+impl Sample {
+    /// The sample as a line in the format, without the line break.
+    pub fn line(&self, format: Format) -> String {
+        let timestamp = self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true);
+        match format {
+            Format::Text => {
+                let mut line = format!(
+                    "{} {} (bus {}, 0x{:02x}): pH {:.3}",
+                    timestamp, self.device, self.bus, self.address, self.ph
+                );
+                if let Some(temperature) = self.temperature {
+                    line.push_str(&format!(" at {:.1} °C", temperature));
+                }
+                line
+            }
+            Format::Json => serde_json::to_string(self).unwrap(),
+            Format::Csv => format!(
+                "{},{},{},{},{:.3},{}",
+                timestamp,
+                csv_field(&self.device),
+                self.bus,
+                self.address,
+                self.ph,
+                self.temperature.map_or(String::new(), |t| t.to_string())
+            ),
+        }
+    }
+}
+
+/// Quotes a CSV field if it holds a comma, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// An output, with its format.
+pub struct Output {
+    format: Format,
+    writer: Box<dyn Write>,
+}
+
+impl Output {
+    /// Writes to `writer`. CSV output starts with a header when `header`
+    /// is set.
+    pub fn new(format: Format, mut writer: Box<dyn Write>, header: bool) -> io::Result<Output> {
+        if format == Format::Csv && header {
+            writeln!(writer, "{}", CSV_HEADER)?;
+            writer.flush()?;
+        }
+        Ok(Output { format, writer })
+    }
+
+    /// Opens the output. Files are appended to, and only get a CSV header
+    /// when they are empty.
+    pub fn open(config: &OutputConfig) -> io::Result<Output> {
+        match *config {
+            OutputConfig::Stdout { format } => Output::new(format, Box::new(io::stdout()), true),
+            OutputConfig::File { ref path, format } => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                let empty = file.metadata()?.len() == 0;
+                Output::new(format, Box::new(file), empty)
+            }
+        }
+    }
+
+    /// Writes the sample, and flushes it.
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        writeln!(self.writer, "{}", sample.line(self.format))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn sample(temperature: Option<f64>) -> Sample {
+        Sample {
+            timestamp: "2018-05-01T12:00:00Z".parse().unwrap(),
+            device: "tank-1".to_string(),
+            bus: 1,
+            address: 0x63,
+            ph: 7.0123,
+            temperature,
+        }
+    }
+
+    #[test]
+    fn formats_samples() {
+        assert_eq!(
+            sample(Some(21.5)).line(Format::Text),
+            "2018-05-01T12:00:00.000Z tank-1 (bus 1, 0x63): pH 7.012 at 21.5 °C"
+        );
+        assert_eq!(
+            sample(None).line(Format::Csv),
+            "2018-05-01T12:00:00.000Z,tank-1,1,99,7.012,"
+        );
+        let mut quoted = sample(None);
+        quoted.device = "tank \"A\", left".to_string();
+        assert_eq!(
+            quoted.line(Format::Csv),
+            "2018-05-01T12:00:00.000Z,\"tank \"\"A\"\", left\",1,99,7.012,"
+        );
+        assert_eq!(
+            sample(None).line(Format::Json),
+            "{\"timestamp\":\"2018-05-01T12:00:00Z\",\"device\":\"tank-1\",\"bus\":1,\
+             \"address\":99,\"ph\":7.0123,\"temperature\":null}"
+        );
+    }
+
+    #[test]
+    fn csv_files_get_a_single_header() {
+        let path = env::temp_dir().join(format!("ezo-ph-logger-{}.csv", process::id()));
+        let _ = fs::remove_file(&path);
+        let config = OutputConfig::File {
+            path: path.clone(),
+            format: Format::Csv,
+        };
+        for _ in 0..2 {
+            let mut output = Output::open(&config).unwrap();
+            output.write(&sample(Some(25.0))).unwrap();
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].ends_with(",7.012,25"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! MQTT publisher for a PH EZO Chip.
+//!
+//! Publishes the readings of the chip under `<prefix>/<name>/`, retains its
+//! calibration, slope and information, and runs the EZO commands sent to
+//! `<prefix>/<name>/command`.
+#[macro_use]
+extern crate clap;
+extern crate chrono;
+extern crate ezo_ph;
+extern crate failure;
+extern crate rumqttc;
+
+mod common;
+
+use std::num::ParseIntError;
+use std::process;
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use clap::{App, Arg};
+use ezo_ph::mqtt::{Publisher, Topics};
+use ezo_ph::sensor::{PhSensor, DEFAULT_ADDRESS};
+use failure::{Error, ResultExt};
+use rumqttc::{Client, MqttOptions};
+
+use common::{device_name, error_chain, is_device_name, parse_number};
+
+/// Default port of MQTT brokers.
+const PORT: u16 = 1883;
+
+/// Messages that can wait for the connection to the broker.
+const CAPACITY: usize = 64;
+
+/// Logs a message to the standard error, with the time.
+fn log(message: &str) {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    eprintln!("{} {}", now, message);
+}
+
+/// Parses `HOST[:PORT]`.
+fn parse_broker(s: &str) -> Result<(String, u16), ParseIntError> {
+    match s.rfind(':') {
+        Some(pos) => Ok((s[..pos].to_string(), s[pos + 1..].parse()?)),
+        None => Ok((s.to_string(), PORT)),
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ezo-ph-mqtt")
+        .version(crate_version!())
+        .about("Publishes the readings and state of a PH EZO Chip over MQTT.")
+        .arg(
+            Arg::with_name("bus")
+                .long("bus")
+                .short("b")
+                .value_name("BUS")
+                .default_value("1")
+                .validator(|s| s.parse::<u8>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Number of the I2C bus, as in /dev/i2c-<BUS>"),
+        )
+        .arg(
+            Arg::with_name("address")
+                .long("address")
+                .short("a")
+                .value_name("ADDRESS")
+                .validator(|s| parse_number(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .help("Address of the chip, in decimal or 0x-prefixed hex [default: 0x63]"),
+        )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .short("n")
+                .value_name("NAME")
+                .validator(|s| {
+                    if is_device_name(&s) {
+                        Ok(())
+                    } else {
+                        Err("the name must not be empty, nor have '/', '+' or '#'".to_string())
+                    }
+                })
+                .help(
+                    "Name of the chip in the topics [default: the chip's own name, or its \
+                     address if it has none]",
+                ),
+        )
+        .arg(
+            Arg::with_name("broker")
+                .long("broker")
+                .value_name("HOST[:PORT]")
+                .default_value("localhost")
+                .validator(|s| parse_broker(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .help("MQTT broker to publish to"),
+        )
+        .arg(
+            Arg::with_name("prefix")
+                .long("prefix")
+                .value_name("PREFIX")
+                .default_value("ezo-ph")
+                .help("Prefix of the topics"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .short("i")
+                .value_name("SECONDS")
+                .default_value("10")
+                .validator(|s| match s.parse::<u64>() {
+                    Ok(0) => Err("the interval must be at least one second".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+                .help("Seconds between readings"),
+        )
+}
+
+fn run() -> Result<(), Error> {
+    let matches = app().get_matches();
+    let bus: u8 = matches.value_of("bus").unwrap().parse()?;
+    let address = match matches.value_of("address") {
+        Some(address) => parse_number(address)?,
+        None => DEFAULT_ADDRESS,
+    };
+    let (host, port) = parse_broker(matches.value_of("broker").unwrap())?;
+    let interval = Duration::from_secs(matches.value_of("interval").unwrap().parse()?);
+
+    let mut sensor = PhSensor::open(bus, address)
+        .with_context(|_| format!("could not open /dev/i2c-{}", bus))?;
+    let name = match matches.value_of("name") {
+        Some(name) => name.to_string(),
+        None => device_name(&mut sensor)
+            .with_context(|_| format!("could not query the name at 0x{:02x}", address))?,
+    };
+
+    let mut options = MqttOptions::new(format!("ezo-ph-{}", name), host.as_str(), port);
+    options.set_keep_alive(Duration::from_secs(30));
+    let (client, mut connection) = Client::new(options, CAPACITY);
+    let topics = Topics::new(matches.value_of("prefix").unwrap(), &name);
+    log(&format!(
+        "publishing {} to {}:{}, on {}",
+        name, host, port, topics.reading
+    ));
+    let mut publisher = Publisher::new(sensor, client, &name, topics);
+    publisher.run(&mut connection, interval, |e| log(&e.to_string()));
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        log(&format!("error: {}", error_chain(&e)));
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_brokers() {
+        assert_eq!(
+            parse_broker("localhost"),
+            Ok(("localhost".to_string(), PORT))
+        );
+        assert_eq!(
+            parse_broker("10.0.0.2:8883"),
+            Ok(("10.0.0.2".to_string(), 8883))
+        );
+        assert!(parse_broker("broker:mqtt").is_err());
+
+        let matches = app()
+            .get_matches_from_safe(vec!["ezo-ph-mqtt", "-n", "tank-1", "-i", "60"])
+            .unwrap();
+        assert_eq!(matches.value_of("broker"), Some("localhost"));
+        assert_eq!(matches.value_of("prefix"), Some("ezo-ph"));
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph-mqtt", "-i", "0"])
+            .is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph-mqtt", "-n", "tank/1"])
+            .is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph-mqtt", "-n", ""])
+            .is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Command-line tool for the PH EZO Chip.
+//!
+//! Each subcommand is turned into an EZO command string and parsed with
+//! `PhCommand::from_str`, so user input is checked the same way as any
+//! other command before it is sent to the chip.
+//!
+//! The `shell` subcommand sends commands typed interactively.
+//!
+//! With `--history`, calibrations are appended to a JSON-lines file.
+#[macro_use]
+extern crate clap;
+extern crate ezo_ph;
+extern crate failure;
+extern crate i2cdev;
+extern crate rustyline;
+#[macro_use]
+extern crate serde_json;
+
+#[path = "../common/mod.rs"]
+mod common;
+mod shell;
+
+use std::process;
+use std::str::FromStr;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use ezo_ph::command::PhCommand;
+use ezo_ph::history::JsonLinesHistory;
+use ezo_ph::response::PhResponse;
+use ezo_ph::sensor::{PhSensor, DEFAULT_ADDRESS};
+use ezo_ph::simulator::Simulator;
+use ezo_ph::transport::EzoTransport;
+use failure::{Error, ResultExt};
+use i2cdev::linux::LinuxI2CDevice;
+use serde_json::Value;
+
+use common::{error_chain, parse_number};
+
+/// How responses are printed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    /// The response as the device sends it.
+    Text,
+    /// A JSON object with the command and its response.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+fn is_number(s: String) -> Result<(), String> {
+    parse_number(&s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn is_bus(s: String) -> Result<(), String> {
+    u8::from_str(&s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    let ph = Arg::with_name("PH")
+        .required(true)
+        .help("pH of the calibration buffer");
+    App::new("ezo-ph")
+        .version(crate_version!())
+        .about("Talks to a PH EZO Chip on an I2C bus.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("bus")
+                .long("bus")
+                .short("b")
+                .value_name("BUS")
+                .default_value("1")
+                .validator(is_bus)
+                .help("Number of the I2C bus, as in /dev/i2c-<BUS>"),
+        )
+        .arg(
+            Arg::with_name("address")
+                .long("address")
+                .short("a")
+                .value_name("ADDRESS")
+                .validator(is_number)
+                .help("Address of the chip, in decimal or 0x-prefixed hex [default: 0x63]"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .value_name("FILE")
+                .help("Appends every calibration to this JSON-lines file"),
+        )
+        .subcommand(SubCommand::with_name("read").about("Takes a pH reading"))
+        .subcommand(SubCommand::with_name("info").about("Shows the device type and firmware"))
+        .subcommand(SubCommand::with_name("status").about("Shows the restart reason and VCC"))
+        .subcommand(
+            SubCommand::with_name("cal")
+                .about("Calibrates the probe")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("mid")
+                        .about("Calibrates the mid point, clearing the others")
+                        .arg(ph.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("low")
+                        .about("Calibrates the low point")
+                        .arg(ph.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("high")
+                        .about("Calibrates the high point")
+                        .arg(ph),
+                )
+                .subcommand(SubCommand::with_name("clear").about("Clears the calibration"))
+                .subcommand(SubCommand::with_name("status").about("Shows the calibrated points")),
+        )
+        .subcommand(SubCommand::with_name("slope").about("Shows the probe slope"))
+        .subcommand(
+            SubCommand::with_name("temp")
+                .about("Sets or shows the temperature compensation")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Sets the temperature compensation")
+                        .setting(AppSettings::AllowNegativeNumbers)
+                        .arg(
+                            Arg::with_name("TEMP")
+                                .required(true)
+                                .help("Temperature, in °C"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("get").about("Shows the temperature compensation"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("sleep").about("Puts the chip to sleep"))
+        .subcommand(
+            SubCommand::with_name("led")
+                .about("Turns the LED on or off, or shows its state")
+                .arg(
+                    Arg::with_name("STATE")
+                        .possible_values(&["on", "off"])
+                        .help("New state of the LED"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("name")
+                .about("Sets the name of the chip, or shows it")
+                .arg(Arg::with_name("NAME").help("New name, up to 16 characters")),
+        )
+        .subcommand(
+            SubCommand::with_name("raw")
+                .about("Sends any command, such as \"Cal,mid,7.00\"")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("COMMAND")
+                        .required(true)
+                        .help("Command string"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("shell")
+                .about("Sends commands typed interactively")
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Talks to a simulated chip instead"),
+                ),
+        )
+}
+
+/// The EZO command string for the subcommand.
+fn command_string(matches: &ArgMatches) -> String {
+    match matches.subcommand() {
+        ("read", _) => "R".to_string(),
+        ("info", _) => "I".to_string(),
+        ("status", _) => "Status".to_string(),
+        ("cal", Some(cal)) => match cal.subcommand() {
+            ("mid", Some(m)) => format!("Cal,mid,{}", m.value_of("PH").unwrap()),
+            ("low", Some(m)) => format!("Cal,low,{}", m.value_of("PH").unwrap()),
+            ("high", Some(m)) => format!("Cal,high,{}", m.value_of("PH").unwrap()),
+            ("clear", _) => "Cal,clear".to_string(),
+            _ => "Cal,?".to_string(),
+        },
+        ("slope", _) => "Slope,?".to_string(),
+        ("temp", Some(temp)) => match temp.subcommand() {
+            ("set", Some(m)) => format!("T,{}", m.value_of("TEMP").unwrap()),
+            _ => "T,?".to_string(),
+        },
+        ("sleep", _) => "Sleep".to_string(),
+        ("led", Some(m)) => match m.value_of("STATE") {
+            Some("on") => "L,1".to_string(),
+            Some("off") => "L,0".to_string(),
+            _ => "L,?".to_string(),
+        },
+        ("name", Some(m)) => match m.value_of("NAME") {
+            Some(name) => format!("Name,{}", name),
+            None => "Name,?".to_string(),
+        },
+        ("raw", Some(m)) => m.value_of("COMMAND").unwrap().to_string(),
+        (name, _) => unreachable!("unknown subcommand: {}", name),
+    }
+}
+
+/// The command for the subcommand, checked by its parser.
+fn command(matches: &ArgMatches) -> Result<PhCommand, Error> {
+    let string = command_string(matches);
+    let cmd =
+        PhCommand::from_str(&string).with_context(|_| format!("invalid command: {:?}", string))?;
+    Ok(cmd)
+}
+
+/// The value of the response, as JSON.
+fn response_json(response: &PhResponse) -> Value {
+    match *response {
+        PhResponse::NoResponse => Value::Null,
+        PhResponse::SensorReading(ref r) => json!(r.0),
+        PhResponse::CompensationValue(ref r) => json!(r.0),
+        PhResponse::ProbeSlope(ref r) => json!({
+            "acid_end": r.acid_end,
+            "base_end": r.base_end,
+            "zero_offset_mv": r.zero_offset_mv,
+        }),
+        PhResponse::DeviceInfo(ref r) => json!({
+            "device": r.device,
+            "firmware": r.firmware,
+        }),
+        PhResponse::DeviceStatus(ref r) => json!({
+            "restart_reason": r.restart_reason.to_string(),
+            "vcc_voltage": r.vcc_voltage,
+        }),
+        PhResponse::ExportedInfo(ref r) => json!({
+            "lines": r.lines,
+            "total_bytes": r.total_bytes,
+        }),
+        ref r => json!(r.to_string()),
+    }
+}
+
+fn print_response(cmd: &PhCommand, response: &PhResponse, format: Format) {
+    match format {
+        Format::Text => match *response {
+            PhResponse::NoResponse => {}
+            ref r => println!("{}", r),
+        },
+        Format::Json => {
+            let output = json!({
+                "command": cmd.to_string(),
+                "response": response_json(response),
+            });
+            println!("{}", output);
+        }
+    }
+}
+
+/// Records calibrations in the file given with `--history`, if any.
+fn set_history<T: EzoTransport>(sensor: &mut PhSensor<T>, matches: &ArgMatches) {
+    if let Some(path) = matches.value_of("history") {
+        sensor.set_history(Some(Box::new(JsonLinesHistory::new(path))));
+    }
+}
+
+fn open(bus: u8, address: u16) -> Result<PhSensor<LinuxI2CDevice>, Error> {
+    let sensor = PhSensor::open(bus, address)
+        .with_context(|_| format!("could not open /dev/i2c-{}", bus))?;
+    Ok(sensor)
+}
+
+fn run() -> Result<(), Error> {
+    let matches = app().get_matches();
+    let bus = u8::from_str(matches.value_of("bus").unwrap())?;
+    let address = match matches.value_of("address") {
+        Some(address) => parse_number(address)?,
+        None => DEFAULT_ADDRESS,
+    };
+    let format = value_t!(matches, "format", Format).unwrap_or_else(|e| e.exit());
+    if let ("shell", Some(m)) = matches.subcommand() {
+        if m.is_present("dry-run") {
+            let mut sensor = PhSensor::new(Simulator::default(), address);
+            set_history(&mut sensor, &matches);
+            return shell::run(&mut sensor);
+        }
+        let mut sensor = open(bus, address)?;
+        set_history(&mut sensor, &matches);
+        return shell::run(&mut sensor);
+    }
+    let cmd = command(&matches)?;
+
+    let mut sensor = open(bus, address)?;
+    set_history(&mut sensor, &matches);
+    let response = sensor.execute(&cmd)?;
+    if let Some(e) = sensor.take_history_error() {
+        eprintln!("warning: calibrated, but not recorded: {}", e);
+    }
+    print_response(&cmd, &response, format);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", error_chain(&e));
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ezo_ph::response::{ProbeSlope, SensorReading};
+
+    fn command_for(args: &[&str]) -> Result<PhCommand, Error> {
+        let mut argv = vec!["ezo-ph"];
+        argv.extend_from_slice(args);
+        let matches = app().get_matches_from_safe(argv).unwrap();
+        command(&matches)
+    }
+
+    fn command_string_for(args: &[&str]) -> String {
+        command_for(args).unwrap().to_string()
+    }
+
+    #[test]
+    fn subcommands_build_commands() {
+        assert_eq!(command_string_for(&["read"]), "R");
+        assert_eq!(command_string_for(&["cal", "mid", "7"]), "CAL,MID,7.00");
+        assert_eq!(command_string_for(&["cal", "status"]), "CAL,?");
+        assert_eq!(command_string_for(&["cal", "clear"]), "CAL,CLEAR");
+        assert_eq!(command_string_for(&["temp", "set", "-2.5"]), "T,-2.500");
+        assert_eq!(command_string_for(&["temp", "get"]), "T,?");
+        assert_eq!(command_string_for(&["led", "on"]), "L,1");
+        assert_eq!(command_string_for(&["led"]), "L,?");
+        assert_eq!(command_string_for(&["name", "tank-3"]), "Name,tank-3");
+        assert_eq!(command_string_for(&["raw", "slope,?"]), "SLOPE,?");
+    }
+
+    #[test]
+    fn invalid_input_is_rejected_by_the_parsers() {
+        assert!(command_for(&["cal", "low", "four"]).is_err());
+        assert!(command_for(&["name", "too,many,commas"]).is_err());
+        assert!(command_for(&["raw", "X,1"]).is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph", "led", "blink"])
+            .is_err());
+    }
+
+    #[test]
+    fn parses_options() {
+        let matches = app()
+            .get_matches_from_safe(vec!["ezo-ph", "--format", "json", "read"])
+            .unwrap();
+        assert_eq!(value_t!(matches, "format", Format).unwrap(), Format::Json);
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph", "--address", "nope", "read"])
+            .is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph", "--bus", "255", "read"])
+            .is_ok());
+        assert!(app()
+            .get_matches_from_safe(vec!["ezo-ph", "--bus", "256", "read"])
+            .is_err());
+    }
+
+    #[test]
+    fn responses_as_json() {
+        let reading = PhResponse::SensorReading(SensorReading(7.012));
+        assert_eq!(response_json(&reading), json!(7.012));
+        let slope = PhResponse::ProbeSlope(ProbeSlope {
+            acid_end: 99.7,
+            base_end: 100.3,
+            zero_offset_mv: None,
+        });
+        assert_eq!(
+            response_json(&slope).to_string(),
+            r#"{"acid_end":99.7,"base_end":100.3,"zero_offset_mv":null}"#
+        );
+        assert_eq!(response_json(&PhResponse::Ack), json!("ok"));
+    }
+}
@@ -0,0 +1,389 @@
+//! Interactive shell, to send raw commands to the chip.
+//!
+//! Every line is parsed with `PhCommand::from_str` before it is sent, and
+//! the reply is shown as the device sent it, with its response code, then
+//! as the parsed response.
+//! Calibrations and imports are made through the sensor, so that they are
+//! recorded in its history, and so is the temperature compensation, so
+//! that the sensor knows it. Their raw reply is not shown.
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ezo_ph::command::{Command, PhCommand, TransportCommand, MAX_DATA};
+use ezo_ph::response::PhResponse;
+use ezo_ph::sensor::PhSensor;
+use ezo_ph::transport::{decode_response, EzoTransport};
+use failure::Error;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+/// Syntax, example and description of the commands, for `help`. The
+/// delay and response type are taken from the parsed example.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("R", "R", "takes a pH reading"),
+    (
+        "RT,<°C>",
+        "RT,25",
+        "takes a reading compensated at the temperature",
+    ),
+    (
+        "Cal,mid,<pH>",
+        "Cal,mid,7.00",
+        "calibrates the mid point, clearing the others",
+    ),
+    ("Cal,low,<pH>", "Cal,low,4.00", "calibrates the low point"),
+    (
+        "Cal,high,<pH>",
+        "Cal,high,10.00",
+        "calibrates the high point",
+    ),
+    ("Cal,clear", "Cal,clear", "clears the calibration"),
+    ("Cal,?", "Cal,?", "shows how many points are calibrated"),
+    ("pHext,1", "pHext,1", "enables the extended pH scale"),
+    ("pHext,0", "pHext,0", "disables the extended pH scale"),
+    ("pHext,?", "pHext,?", "shows the pH scale"),
+    ("Slope,?", "Slope,?", "shows the probe slope"),
+    ("T,<°C>", "T,25", "sets the temperature compensation"),
+    ("T,?", "T,?", "shows the temperature compensation"),
+    ("Name,<name>", "Name,tank", "sets the name of the chip"),
+    ("Name,?", "Name,?", "shows the name of the chip"),
+    (
+        "I2C,<address>",
+        "I2C,99",
+        "changes the I2C address, and reboots",
+    ),
+    (
+        "Baud,<rate>",
+        "Baud,9600",
+        "switches to UART mode at the rate, and reboots",
+    ),
+    ("I", "I", "shows the device type and firmware"),
+    (
+        "Export,?",
+        "Export,?",
+        "shows how much calibration data there is",
+    ),
+    (
+        "Export",
+        "Export",
+        "exports the next string of calibration data",
+    ),
+    (
+        "Import,<string>",
+        "Import,MP7.000",
+        "imports a string of calibration data",
+    ),
+    (
+        "Factory",
+        "Factory",
+        "resets to factory settings, and reboots",
+    ),
+    ("F", "F", "blinks the LED to find the chip"),
+    ("L,1", "L,1", "turns the LED on"),
+    ("L,0", "L,0", "turns the LED off"),
+    ("L,?", "L,?", "shows the LED state"),
+    ("Plock,1", "Plock,1", "locks the protocol to I2C"),
+    ("Plock,0", "Plock,0", "unlocks the protocol"),
+    ("Plock,?", "Plock,?", "shows the protocol lock"),
+    ("Sleep", "Sleep", "puts the chip to sleep"),
+    ("Status", "Status", "shows the restart reason and VCC"),
+];
+
+/// Help for a command, or for the commands whose syntax starts with
+/// `topic`, or for all of them.
+fn help(topic: Option<&str>) -> Vec<String> {
+    let describe = |cmd: &PhCommand| format!("{:>4} ms, {}", cmd.get_delay(), cmd.response_type());
+    let topic = match topic {
+        Some(topic) => topic,
+        None => {
+            let mut lines: Vec<String> = COMMANDS
+                .iter()
+                .map(|&(syntax, example, description)| {
+                    let cmd = PhCommand::from_str(example).unwrap();
+                    format!("{:<16} {:<28} {}", syntax, describe(&cmd), description)
+                })
+                .collect();
+            lines.push("help [<command>]: shows this, or the help of a command".to_string());
+            lines.push("quit: leaves the shell".to_string());
+            return lines;
+        }
+    };
+    if let Ok(cmd) = PhCommand::from_str(topic) {
+        return vec![format!("{}: {}", cmd, describe(&cmd))];
+    }
+    let topic = topic.to_uppercase();
+    let lines: Vec<String> = COMMANDS
+        .iter()
+        .filter(|&&(syntax, _, _)| {
+            let syntax = syntax.to_uppercase();
+            syntax == topic || syntax.starts_with(&format!("{},", topic))
+        })
+        .map(|&(syntax, example, description)| {
+            let cmd = PhCommand::from_str(example).unwrap();
+            format!("{:<16} {:<28} {}", syntax, describe(&cmd), description)
+        })
+        .collect();
+    if lines.is_empty() {
+        vec![format!("unknown command: {}", topic)]
+    } else {
+        lines
+    }
+}
+
+/// Runs a line typed in the shell, and writes its outcome. Returns
+/// `false` when the shell should be left.
+fn execute<T: EzoTransport, W: Write>(
+    sensor: &mut PhSensor<T>,
+    line: &str,
+    out: &mut W,
+) -> io::Result<bool> {
+    let line = line.trim();
+    let lower = line.to_lowercase();
+    if line.is_empty() {
+        return Ok(true);
+    }
+    if lower == "quit" || lower == "exit" {
+        return Ok(false);
+    }
+    if lower == "help" || lower.starts_with("help ") {
+        let topic = line.get(5..).map(|t| t.trim()).filter(|t| !t.is_empty());
+        for line in help(topic) {
+            writeln!(out, "{}", line)?;
+        }
+        return Ok(true);
+    }
+    let cmd = match PhCommand::from_str(line) {
+        Ok(cmd) => cmd,
+        Err(_) => {
+            writeln!(out, "invalid command: {} (try `help`)", line)?;
+            return Ok(true);
+        }
+    };
+    let reply = match exchange(sensor, &cmd) {
+        Ok(reply) => reply,
+        Err(e) => {
+            writeln!(out, "error: {}", e)?;
+            return Ok(true);
+        }
+    };
+    let data = match reply {
+        Reply::Raw(Some(ref buf)) => {
+            let data = decode_response(buf);
+            let shown = match data {
+                Ok(ref data) => format!(" {:?}", data),
+                Err(_) => String::new(),
+            };
+            writeln!(out, "raw:     {} ({}){}", buf[0], code_name(buf[0]), shown)?;
+            data
+        }
+        Reply::Raw(None) => {
+            writeln!(out, "raw:     no reply")?;
+            Ok(String::new())
+        }
+        Reply::Sensor(response) => {
+            writeln!(out, "sensor:  sent through the sensor")?;
+            writeln!(out, "debug:   {:?}", response)?;
+            writeln!(out, "display: {}", response)?;
+            if let Some(e) = sensor.take_history_error() {
+                writeln!(out, "warning: calibrated, but not recorded: {}", e)?;
+            }
+            return Ok(true);
+        }
+    };
+    match data.and_then(|data| cmd.parse_response(&data)) {
+        Ok(response) => {
+            writeln!(out, "debug:   {:?}", response)?;
+            writeln!(out, "display: {}", response)?;
+        }
+        Err(e) => writeln!(out, "error:   {}", e)?,
+    }
+    Ok(true)
+}
+
+/// The outcome of a command sent from the shell.
+enum Reply {
+    /// The reply as read from the device, or `None` for commands it does
+    /// not reply to.
+    Raw(Option<Vec<u8>>),
+    /// The response of a command run through the sensor.
+    Sensor(PhResponse),
+}
+
+/// Sends the command. Calibrations, imports and the temperature
+/// compensation go through the sensor; the other commands are sent as
+/// they are, and their reply is read from the device.
+fn exchange<T: EzoTransport>(sensor: &mut PhSensor<T>, cmd: &PhCommand) -> Result<Reply, Error> {
+    match *cmd {
+        PhCommand::CalibrationMid(_)
+        | PhCommand::CalibrationLow(_)
+        | PhCommand::CalibrationHigh(_)
+        | PhCommand::CalibrationClear(_)
+        | PhCommand::Import(_)
+        | PhCommand::TemperatureCompensation(_) => {
+            return Ok(Reply::Sensor(sensor.execute(cmd)?));
+        }
+        _ => {}
+    }
+    let transport = sensor.transport_mut();
+    transport.write_command(&cmd.get_command_string())?;
+    if !cmd.expects_response() {
+        return Ok(Reply::Raw(None));
+    }
+    let mut buf = vec![0u8; MAX_DATA];
+    transport.wait(cmd.get_delay());
+    transport.read_response(&mut buf)?;
+    Ok(Reply::Raw(Some(buf)))
+}
+
+/// What the response code of a reply means.
+fn code_name(code: u8) -> &'static str {
+    match code {
+        1 => "ok",
+        2 => "error",
+        254 => "pending",
+        255 => "no data",
+        _ => "unknown",
+    }
+}
+
+/// File the history of the shell is kept in, if there is a home directory.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".ezo-ph_history"))
+}
+
+/// Reads commands until `quit`, or the end of the input.
+pub fn run<T: EzoTransport>(sensor: &mut PhSensor<T>) -> Result<(), Error> {
+    let mut editor = Editor::<()>::new();
+    let history = history_path();
+    if let Some(ref path) = history {
+        // There is no history yet the first time.
+        let _ = editor.load_history(path);
+    }
+    let stdout = io::stdout();
+    loop {
+        let line = match editor.readline("ezo-ph> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str());
+        }
+        if !execute(sensor, &line, &mut stdout.lock())? {
+            break;
+        }
+    }
+    if let Some(ref path) = history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ezo_ph::history::MemoryHistory;
+    use ezo_ph::mock::MockDevice;
+    use ezo_ph::sensor::DEFAULT_ADDRESS;
+    use ezo_ph::simulator::Simulator;
+
+    fn output<T: EzoTransport>(sensor: &mut PhSensor<T>, line: &str) -> String {
+        let mut out = Vec::new();
+        assert!(execute(sensor, line, &mut out).unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn shows_raw_and_parsed_reply() {
+        let mut sim = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(
+            output(&mut sim, "cal,mid,7"),
+            "sensor:  sent through the sensor\ndebug:   *OK\ndisplay: ok\n"
+        );
+        assert_eq!(
+            output(&mut sim, "CAL,?"),
+            "raw:     1 (ok) \"?CAL,1\"\ndebug:   ?CAL,1\ndisplay: one-point\n"
+        );
+    }
+
+    #[test]
+    fn records_calibrations_in_history() {
+        let mut sim = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sim.set_history(Some(Box::new(MemoryHistory::new())));
+        output(&mut sim, "cal,mid,7");
+        output(&mut sim, "r");
+        let entries = sim.history().unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].ph, Some(7.0));
+
+        let mut dev = MockDevice::new();
+        dev.queue_data("?SLOPE,100.0,100.0")
+            .queue_data("?T,25.000")
+            .queue_ack()
+            .queue_code(2);
+        let mut dev = PhSensor::new(dev, DEFAULT_ADDRESS);
+        dev.set_history(Some(Box::new(MemoryHistory::new())));
+        let out = output(&mut dev, "cal,mid,7");
+        assert!(out.contains("display: ok\nwarning: calibrated, but not recorded: "));
+    }
+
+    #[test]
+    fn sets_the_temperature_through_the_sensor() {
+        let mut sim = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(
+            output(&mut sim, "t,21.5"),
+            "sensor:  sent through the sensor\ndebug:   *OK\ndisplay: ok\n"
+        );
+        assert_eq!(sim.last_temperature(), Some(21.5));
+    }
+
+    #[test]
+    fn validates_commands_before_sending() {
+        let mut dev = PhSensor::new(MockDevice::new(), DEFAULT_ADDRESS);
+        assert_eq!(
+            output(&mut dev, "t,hot"),
+            "invalid command: t,hot (try `help`)\n"
+        );
+        assert!(dev.transport().commands().is_empty());
+
+        dev.transport_mut().queue_data("?SLOPE,bad");
+        let out = output(&mut dev, "slope,?");
+        assert!(out.starts_with("raw:     1 (ok) \"?SLOPE,bad\"\nerror:   "));
+        assert_eq!(dev.transport().commands(), vec!["SLOPE,?"]);
+    }
+
+    #[test]
+    fn shows_response_codes() {
+        let mut dev = PhSensor::new(MockDevice::new(), DEFAULT_ADDRESS);
+        dev.transport_mut().queue_code(2).queue_code(254);
+        assert!(output(&mut dev, "r").starts_with("raw:     2 (error)\nerror:   "));
+        assert!(output(&mut dev, "i").starts_with("raw:     254 (pending)\nerror:   "));
+        assert!(output(&mut dev, "sleep").starts_with("raw:     no reply\n"));
+        assert_eq!(dev.transport().commands(), vec!["R", "I", "SLEEP"]);
+    }
+
+    #[test]
+    fn help_shows_delay_and_response_type() {
+        assert_eq!(help(Some("r")), vec!["R:  900 ms, SensorReading"]);
+        let lines = help(Some("cal"));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("Cal,mid,<pH>      900 ms, Ack"));
+        assert_eq!(help(Some("t")).len(), 2);
+        let lines = help(Some("baud"));
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("Baud,<rate>"));
+        assert_eq!(help(Some("foo")), vec!["unknown command: FOO"]);
+        // Every example parses.
+        assert_eq!(help(None).len(), COMMANDS.len() + 2);
+    }
+
+    #[test]
+    fn quits() {
+        let mut sim = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert!(!execute(&mut sim, " quit ", &mut Vec::new()).unwrap());
+        assert!(execute(&mut sim, "", &mut Vec::new()).unwrap());
+    }
+}
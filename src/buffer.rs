@@ -0,0 +1,293 @@
+//! Calibration buffers, and how their pH varies with temperature.
+//!
+//! A buffer's pH is only its nominal value at 25 °C. The tables below give
+//! the pH of each buffer every 5 °C, and values in between are
+//! interpolated, so the chip is calibrated to the buffer's actual pH at
+//! the temperature used for compensation.
+use super::calibration::CalibrationPoint;
+use super::command::{CalibrationHigh, CalibrationLow, CalibrationMid, PhCommand};
+use super::response::{CompensationValue, SensorReading};
+
+/// Default largest difference, in pH, between a reading and a buffer's
+/// nominal pH, for the probe to be considered in that buffer.
+pub const RECOGNITION_TOLERANCE: f64 = 1.0;
+
+/// A calibration buffer solution.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Buffer {
+    /// Name of the buffer.
+    pub name: &'static str,
+    /// pH of the buffer at 25 °C.
+    pub nominal: f64,
+    /// Calibration point the buffer is used for.
+    pub point: CalibrationPoint,
+    /// pH of the buffer, by temperature in °C, sorted by temperature.
+    pub table: &'static [(f64, f64)],
+}
+
+impl Buffer {
+    /// pH of the buffer at the given temperature, in °C. Outside of the
+    /// table, the closest value is used.
+    pub fn ph_at(&self, temperature: f64) -> f64 {
+        let table = self.table;
+        if table.is_empty() {
+            return self.nominal;
+        }
+        let (first_t, first_ph) = table[0];
+        if temperature <= first_t {
+            return first_ph;
+        }
+        for pair in table.windows(2) {
+            let (t0, ph0) = pair[0];
+            let (t1, ph1) = pair[1];
+            if temperature <= t1 {
+                return ph0 + (ph1 - ph0) * (temperature - t0) / (t1 - t0);
+            }
+        }
+        table[table.len() - 1].1
+    }
+
+    /// Whether the reading is within `tolerance` pH of the buffer's
+    /// nominal pH.
+    pub fn matches(&self, reading: SensorReading, tolerance: f64) -> bool {
+        (reading.0 - self.nominal).abs() <= tolerance
+    }
+
+    /// pH to calibrate the buffer's point to, given the temperature used
+    /// for compensation.
+    pub fn calibration_value(&self, compensation: CompensationValue) -> f64 {
+        self.ph_at(compensation.0)
+    }
+
+    /// The `CAL,...` command that calibrates the buffer's point, given
+    /// the temperature used for compensation.
+    pub fn calibration_command(&self, compensation: CompensationValue) -> PhCommand {
+        let ph = self.calibration_value(compensation);
+        match self.point {
+            CalibrationPoint::Mid => CalibrationMid(ph).into(),
+            CalibrationPoint::Low => CalibrationLow(ph).into(),
+            CalibrationPoint::High => CalibrationHigh(ph).into(),
+        }
+    }
+}
+
+/// Buffers for the low, mid and high calibration points.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BufferSet {
+    /// Name of the set.
+    pub name: &'static str,
+    pub low: Buffer,
+    pub mid: Buffer,
+    pub high: Buffer,
+}
+
+impl BufferSet {
+    /// The buffer used for the given calibration point.
+    pub fn buffer(&self, point: CalibrationPoint) -> &Buffer {
+        match point {
+            CalibrationPoint::Mid => &self.mid,
+            CalibrationPoint::Low => &self.low,
+            CalibrationPoint::High => &self.high,
+        }
+    }
+
+    /// The buffer of the set closest to the reading, if the reading is
+    /// within `tolerance` pH of its nominal pH.
+    pub fn recognize(&self, reading: SensorReading, tolerance: f64) -> Option<&Buffer> {
+        self.buffers()
+            .iter()
+            .cloned()
+            .filter(|buffer| buffer.matches(reading, tolerance))
+            .min_by(|a, b| {
+                let a = (reading.0 - a.nominal).abs();
+                let b = (reading.0 - b.nominal).abs();
+                a.partial_cmp(&b).unwrap()
+            })
+    }
+
+    /// The buffers, in calibration order.
+    pub fn buffers(&self) -> [&Buffer; 3] {
+        [&self.mid, &self.low, &self.high]
+    }
+}
+
+/// NIST standard buffers, as specified by DIN 19266.
+pub const NIST: BufferSet = BufferSet {
+    name: "NIST/DIN 19266",
+    low: Buffer {
+        name: "potassium hydrogen phthalate 0.05 mol/kg",
+        nominal: 4.005,
+        point: CalibrationPoint::Low,
+        table: &[
+            (0.0, 4.000),
+            (5.0, 3.998),
+            (10.0, 3.997),
+            (15.0, 3.998),
+            (20.0, 4.000),
+            (25.0, 4.005),
+            (30.0, 4.011),
+            (35.0, 4.018),
+            (40.0, 4.027),
+            (45.0, 4.038),
+            (50.0, 4.050),
+            (55.0, 4.064),
+            (60.0, 4.080),
+        ],
+    },
+    mid: Buffer {
+        name: "phosphate 0.025 mol/kg",
+        nominal: 6.865,
+        point: CalibrationPoint::Mid,
+        table: &[
+            (0.0, 6.984),
+            (5.0, 6.951),
+            (10.0, 6.923),
+            (15.0, 6.900),
+            (20.0, 6.881),
+            (25.0, 6.865),
+            (30.0, 6.853),
+            (35.0, 6.844),
+            (40.0, 6.838),
+            (45.0, 6.834),
+            (50.0, 6.833),
+            (55.0, 6.834),
+            (60.0, 6.836),
+        ],
+    },
+    high: Buffer {
+        name: "borax 0.01 mol/kg",
+        nominal: 9.180,
+        point: CalibrationPoint::High,
+        table: &[
+            (0.0, 9.464),
+            (5.0, 9.395),
+            (10.0, 9.332),
+            (15.0, 9.276),
+            (20.0, 9.225),
+            (25.0, 9.180),
+            (30.0, 9.139),
+            (35.0, 9.102),
+            (40.0, 9.068),
+            (45.0, 9.038),
+            (50.0, 9.011),
+            (55.0, 8.985),
+            (60.0, 8.962),
+        ],
+    },
+};
+
+/// Common commercial technical buffers, of pH 4.01, 7.00 and 10.01.
+pub const TECHNICAL: BufferSet = BufferSet {
+    name: "4.01/7.00/10.01",
+    low: Buffer {
+        name: "pH 4.01",
+        nominal: 4.01,
+        point: CalibrationPoint::Low,
+        table: &[
+            (0.0, 4.01),
+            (5.0, 4.00),
+            (10.0, 4.00),
+            (15.0, 4.00),
+            (20.0, 4.00),
+            (25.0, 4.01),
+            (30.0, 4.01),
+            (35.0, 4.02),
+            (40.0, 4.03),
+            (45.0, 4.04),
+            (50.0, 4.06),
+        ],
+    },
+    mid: Buffer {
+        name: "pH 7.00",
+        nominal: 7.00,
+        point: CalibrationPoint::Mid,
+        table: &[
+            (0.0, 7.12),
+            (5.0, 7.09),
+            (10.0, 7.06),
+            (15.0, 7.04),
+            (20.0, 7.02),
+            (25.0, 7.00),
+            (30.0, 6.99),
+            (35.0, 6.98),
+            (40.0, 6.97),
+            (45.0, 6.97),
+            (50.0, 6.97),
+        ],
+    },
+    high: Buffer {
+        name: "pH 10.01",
+        nominal: 10.01,
+        point: CalibrationPoint::High,
+        table: &[
+            (0.0, 10.32),
+            (5.0, 10.25),
+            (10.0, 10.18),
+            (15.0, 10.12),
+            (20.0, 10.06),
+            (25.0, 10.01),
+            (30.0, 9.97),
+            (35.0, 9.93),
+            (40.0, 9.89),
+            (45.0, 9.86),
+            (50.0, 9.83),
+        ],
+    },
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::Command;
+
+    #[test]
+    fn interpolates_between_table_entries() {
+        assert_eq!(NIST.low.ph_at(25.0), 4.005);
+        assert!((NIST.high.ph_at(22.5) - 9.2025).abs() < 1e-9);
+        assert!((TECHNICAL.mid.ph_at(17.0) - 7.032).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clamps_outside_of_table() {
+        assert_eq!(NIST.mid.ph_at(-5.0), 6.984);
+        assert_eq!(TECHNICAL.high.ph_at(80.0), 9.83);
+    }
+
+    #[test]
+    fn buffers_match_their_points() {
+        for set in &[NIST, TECHNICAL] {
+            for &point in &[
+                CalibrationPoint::Mid,
+                CalibrationPoint::Low,
+                CalibrationPoint::High,
+            ] {
+                let buffer = set.buffer(point);
+                assert_eq!(buffer.point, point);
+                assert_eq!(buffer.ph_at(25.0), buffer.nominal);
+            }
+        }
+    }
+
+    #[test]
+    fn recognizes_closest_buffer() {
+        let found = NIST.recognize(SensorReading(6.5), RECOGNITION_TOLERANCE);
+        assert_eq!(found, Some(&NIST.mid));
+        let found = TECHNICAL.recognize(SensorReading(9.1), RECOGNITION_TOLERANCE);
+        assert_eq!(found, Some(&TECHNICAL.high));
+        assert_eq!(TECHNICAL.recognize(SensorReading(5.5), 1.0), None);
+        assert_eq!(
+            TECHNICAL.recognize(SensorReading(5.5), 2.0),
+            Some(&TECHNICAL.low)
+        );
+    }
+
+    #[test]
+    fn builds_calibration_command_for_temperature() {
+        let cmd = TECHNICAL.high.calibration_command(CompensationValue(20.0));
+        assert_eq!(cmd, PhCommand::CalibrationHigh(CalibrationHigh(10.06)));
+        assert_eq!(cmd.get_command_string(), "CAL,HIGH,10.06");
+
+        let cmd = NIST.mid.calibration_command(CompensationValue(25.0));
+        assert_eq!(cmd.get_command_string(), "CAL,MID,6.87");
+    }
+}
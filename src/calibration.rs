@@ -0,0 +1,492 @@
+//! Guided calibration of the PH EZO Chip.
+//!
+//! The datasheet requires the mid point to be calibrated first, because it
+//! clears the low and high points. The low point comes next, for a
+//! two-point calibration, and then the high point, for a three-point one.
+use std::fmt;
+use std::time::Duration;
+
+use super::buffer::{Buffer, BufferSet, RECOGNITION_TOLERANCE};
+use super::history::HistoryError;
+use super::response::{CalibrationStatus, ProbeSlope, SensorReading};
+use super::sensor::PhSensor;
+use super::stability::{Stability, StabilityError};
+use super::transport::EzoTransport;
+use super::EzoError;
+
+use failure::Fail;
+
+/// A calibration point of the PH EZO Chip.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CalibrationPoint {
+    Mid,
+    Low,
+    High,
+}
+
+impl CalibrationPoint {
+    /// Calibration status of the device after this point is calibrated.
+    pub fn status(&self) -> CalibrationStatus {
+        match *self {
+            CalibrationPoint::Mid => CalibrationStatus::OnePoint,
+            CalibrationPoint::Low => CalibrationStatus::TwoPoint,
+            CalibrationPoint::High => CalibrationStatus::ThreePoint,
+        }
+    }
+
+    /// The point that is calibrated after this one, if any.
+    pub fn next(&self) -> Option<CalibrationPoint> {
+        match *self {
+            CalibrationPoint::Mid => Some(CalibrationPoint::Low),
+            CalibrationPoint::Low => Some(CalibrationPoint::High),
+            CalibrationPoint::High => None,
+        }
+    }
+}
+
+impl fmt::Display for CalibrationPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationPoint::Mid => write!(f, "mid"),
+            CalibrationPoint::Low => write!(f, "low"),
+            CalibrationPoint::High => write!(f, "high"),
+        }
+    }
+}
+
+/// A calibration of the sensor, one point at a time, in the order
+/// required by the datasheet.
+///
+/// Before each point is calibrated, readings are taken until they settle
+/// in the buffer solution. After it is calibrated, the device is queried
+/// to check that it stored the point.
+pub struct CalibrationSession<'a, T: EzoTransport + 'a> {
+    sensor: &'a mut PhSensor<T>,
+    stability: Stability,
+    tolerance: f64,
+    next: Option<CalibrationPoint>,
+}
+
+impl<'a, T: EzoTransport> CalibrationSession<'a, T> {
+    /// Starts a calibration of the sensor, from the mid point.
+    pub fn new(sensor: &'a mut PhSensor<T>) -> CalibrationSession<'a, T> {
+        CalibrationSession {
+            sensor,
+            stability: Stability::default(),
+            tolerance: RECOGNITION_TOLERANCE,
+            next: Some(CalibrationPoint::Mid),
+        }
+    }
+
+    /// Sets when readings are considered stable.
+    pub fn set_stability(&mut self, stability: Stability) {
+        self.stability = stability;
+    }
+
+    /// Sets how far, in pH, a settled reading may be from a buffer's
+    /// nominal pH for the probe to be considered in that buffer.
+    pub fn set_recognition_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    /// The point to be calibrated next, or `None` once the high point has
+    /// been calibrated.
+    pub fn next_point(&self) -> Option<CalibrationPoint> {
+        self.next
+    }
+
+    /// Calibrates `point` to the given pH, once the reading is stable.
+    /// Returns the settled reading, taken before calibrating.
+    pub fn calibrate(
+        &mut self,
+        point: CalibrationPoint,
+        ph: f64,
+    ) -> Result<SensorReading, CalibrationError> {
+        self.check_order(point)?;
+        let reading = self.sensor.read_stable(&self.stability)?.reading;
+        self.apply(point, ph)?;
+        Ok(reading)
+    }
+
+    /// Calibrates the buffer's point to the buffer's pH at the temperature
+    /// used for compensation, once the reading is stable. Fails if the
+    /// settled reading does not match the buffer.
+    pub fn calibrate_buffer(&mut self, buffer: &Buffer) -> Result<SensorReading, CalibrationError> {
+        self.check_order(buffer.point)?;
+        let reading = self.sensor.read_stable(&self.stability)?.reading;
+        if !buffer.matches(reading, self.tolerance) {
+            return Err(CalibrationError::WrongBuffer {
+                expected: buffer.point,
+                found: None,
+                reading,
+            });
+        }
+        self.apply_buffer(buffer)?;
+        Ok(reading)
+    }
+
+    /// Recognizes which buffer of the set the probe is in, once the
+    /// reading is stable, and calibrates its point if it is the next one.
+    /// Returns the buffer that was calibrated.
+    pub fn calibrate_recognized(
+        &mut self,
+        buffers: &BufferSet,
+    ) -> Result<Buffer, CalibrationError> {
+        let expected = match self.next {
+            Some(point) => point,
+            None => return Err(CalibrationError::Complete),
+        };
+        let reading = self.sensor.read_stable(&self.stability)?.reading;
+        match buffers.recognize(reading, self.tolerance) {
+            Some(buffer) if buffer.point == expected => {
+                self.apply_buffer(buffer)?;
+                Ok(*buffer)
+            }
+            found => Err(CalibrationError::WrongBuffer {
+                expected,
+                found: found.cloned(),
+                reading,
+            }),
+        }
+    }
+
+    /// Ends the calibration, returning the resulting probe slope.
+    pub fn finish(self) -> Result<ProbeSlope, CalibrationError> {
+        if self.next == Some(CalibrationPoint::Mid) {
+            return Err(CalibrationError::NotCalibrated);
+        }
+        let slope = self.sensor.slope()?;
+        Ok(slope)
+    }
+
+    fn check_order(&self, point: CalibrationPoint) -> Result<(), CalibrationError> {
+        match self.next {
+            Some(next) if next == point => Ok(()),
+            Some(next) => Err(CalibrationError::OutOfOrder {
+                expected: next,
+                requested: point,
+            }),
+            None => Err(CalibrationError::Complete),
+        }
+    }
+
+    /// Calibrates the point, and checks that the device stored it. If the
+    /// calibration could not be recorded in the history, the session still
+    /// moves on to the next point.
+    fn apply(&mut self, point: CalibrationPoint, ph: f64) -> Result<(), CalibrationError> {
+        match point {
+            CalibrationPoint::Mid => self.sensor.calibrate_mid(ph)?,
+            CalibrationPoint::Low => self.sensor.calibrate_low(ph)?,
+            CalibrationPoint::High => self.sensor.calibrate_high(ph)?,
+        }
+        let unrecorded = self.sensor.take_history_error();
+        let status = self.sensor.calibration_status()?;
+        if status != point.status() {
+            return Err(CalibrationError::NotApplied { point, status });
+        }
+        self.next = point.next();
+        match unrecorded {
+            Some(e) => Err(CalibrationError::NotRecorded(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_buffer(&mut self, buffer: &Buffer) -> Result<(), CalibrationError> {
+        let compensation = self.sensor.temperature()?;
+        self.apply(buffer.point, buffer.calibration_value(compensation))
+    }
+}
+
+/// Error returned by a `CalibrationSession`.
+#[derive(Debug)]
+pub enum CalibrationError {
+    /// The point requested is not the one to be calibrated next.
+    OutOfOrder {
+        expected: CalibrationPoint,
+        requested: CalibrationPoint,
+    },
+    /// All the points have already been calibrated.
+    Complete,
+    /// The settled reading does not match the buffer for the point, and
+    /// matches the `found` buffer instead, if any.
+    WrongBuffer {
+        expected: CalibrationPoint,
+        found: Option<Buffer>,
+        reading: SensorReading,
+    },
+    /// The reading did not settle before the timeout.
+    Unstable { readings: usize, elapsed: Duration },
+    /// The device did not report the calibration status expected after
+    /// calibrating the point.
+    NotApplied {
+        point: CalibrationPoint,
+        status: CalibrationStatus,
+    },
+    /// The session was finished before the mid point was calibrated.
+    NotCalibrated,
+    /// The point was calibrated, but could not be recorded in the history.
+    NotRecorded(HistoryError),
+    /// Communicating with the device failed.
+    Device(EzoError),
+}
+
+impl From<EzoError> for CalibrationError {
+    fn from(error: EzoError) -> CalibrationError {
+        CalibrationError::Device(error)
+    }
+}
+
+impl From<StabilityError> for CalibrationError {
+    fn from(error: StabilityError) -> CalibrationError {
+        match error {
+            StabilityError::Unsettled { readings, elapsed } => {
+                CalibrationError::Unstable { readings, elapsed }
+            }
+            StabilityError::Device(e) => CalibrationError::Device(e),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationError::OutOfOrder {
+                expected,
+                requested,
+            } => write!(
+                f,
+                "cannot calibrate the {} point before the {} point",
+                requested, expected
+            ),
+            CalibrationError::Complete => write!(f, "all points are already calibrated"),
+            CalibrationError::WrongBuffer {
+                expected,
+                found: Some(ref found),
+                reading,
+            } => write!(
+                f,
+                "reading of pH {:.3} is in the {} buffer of the {} point, not the {} point",
+                reading.0, found.name, found.point, expected
+            ),
+            CalibrationError::WrongBuffer {
+                expected,
+                found: None,
+                reading,
+            } => write!(
+                f,
+                "reading of pH {:.3} does not match the buffer for the {} point",
+                reading.0, expected
+            ),
+            CalibrationError::Unstable { readings, .. } => {
+                write!(f, "reading was not stable after {} readings", readings)
+            }
+            CalibrationError::NotApplied { point, status } => write!(
+                f,
+                "{} point was not calibrated, calibration status is {}",
+                point, status
+            ),
+            CalibrationError::NotCalibrated => write!(f, "no point was calibrated"),
+            CalibrationError::NotRecorded(ref e) => {
+                write!(f, "point was calibrated, but not recorded: {}", e)
+            }
+            CalibrationError::Device(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Fail for CalibrationError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            CalibrationError::Device(ref e) => Some(e),
+            CalibrationError::NotRecorded(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffer::{NIST, TECHNICAL};
+    use mock::MockDevice;
+    use sensor::DEFAULT_ADDRESS;
+    use simulator::{ProbeModel, Simulator};
+
+    fn noisy_sensor() -> PhSensor<Simulator> {
+        let probe = ProbeModel {
+            offset_mv: 15.0,
+            acid_slope: 98.0,
+            base_slope: 96.0,
+            noise_mv: 0.5,
+            ..ProbeModel::default()
+        };
+        PhSensor::new(Simulator::new(probe), DEFAULT_ADDRESS)
+    }
+
+    #[test]
+    fn calibrates_three_points_in_order() {
+        let mut sensor = noisy_sensor();
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            assert_eq!(session.next_point(), Some(CalibrationPoint::Mid));
+            for &(point, ph) in &[
+                (CalibrationPoint::Mid, 7.0),
+                (CalibrationPoint::Low, 4.0),
+                (CalibrationPoint::High, 10.0),
+            ] {
+                session.sensor.transport_mut().set_solution_ph(ph);
+                session.calibrate(point, ph).unwrap();
+            }
+            assert_eq!(session.next_point(), None);
+
+            let slope = session.finish().unwrap();
+            assert!((slope.acid_end - 98.0).abs() < 0.5);
+            assert!((slope.base_end - 96.0).abs() < 0.5);
+        }
+        assert_eq!(
+            sensor.last_calibration(),
+            Some(CalibrationStatus::ThreePoint)
+        );
+    }
+
+    #[test]
+    fn calibrates_buffers_at_compensation_temperature() {
+        let mut sensor = noisy_sensor();
+        sensor.set_temperature(20.0).unwrap();
+        {
+            let probe = sensor.transport_mut().probe_mut();
+            probe.temperature = 20.0;
+            probe.noise_mv = 0.0;
+        }
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            for buffer in TECHNICAL.buffers().iter() {
+                let ph = buffer.ph_at(20.0);
+                session.sensor.transport_mut().set_solution_ph(ph);
+                session.calibrate_buffer(buffer).unwrap();
+            }
+            session.finish().unwrap();
+        }
+        sensor.transport_mut().set_solution_ph(7.02);
+        let reading = sensor.read().unwrap();
+        assert!((reading.0 - 7.02).abs() < 0.001);
+    }
+
+    #[test]
+    fn recognizes_buffers() {
+        let mut sensor = noisy_sensor();
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            for buffer in NIST.buffers().iter() {
+                session
+                    .sensor
+                    .transport_mut()
+                    .set_solution_ph(buffer.nominal);
+                let recognized = session.calibrate_recognized(&NIST).unwrap();
+                assert_eq!(recognized.name, buffer.name);
+            }
+            match session.calibrate_recognized(&NIST).unwrap_err() {
+                CalibrationError::Complete => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+        assert_eq!(
+            sensor.last_calibration(),
+            Some(CalibrationStatus::ThreePoint)
+        );
+    }
+
+    #[test]
+    fn refuses_wrong_buffer() {
+        let mut sensor = noisy_sensor();
+        sensor.transport_mut().set_solution_ph(4.01);
+        let mut session = CalibrationSession::new(&mut sensor);
+        let err = session.calibrate_recognized(&TECHNICAL).unwrap_err();
+        match err {
+            CalibrationError::WrongBuffer {
+                expected: CalibrationPoint::Mid,
+                found: Some(found),
+                ..
+            } => assert_eq!(found, TECHNICAL.low),
+            ref e => panic!("unexpected error: {}", e),
+        }
+        assert!(err
+            .to_string()
+            .ends_with("is in the pH 4.01 buffer of the low point, not the mid point"));
+
+        session.sensor.transport_mut().set_solution_ph(2.0);
+        let err = session.calibrate_buffer(&TECHNICAL.mid).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("does not match the buffer for the mid point"));
+        assert_eq!(session.next_point(), Some(CalibrationPoint::Mid));
+    }
+
+    #[test]
+    fn refuses_points_out_of_order() {
+        let mut sensor = PhSensor::new(MockDevice::new(), DEFAULT_ADDRESS);
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            let err = session.calibrate(CalibrationPoint::Low, 4.0).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "cannot calibrate the low point before the mid point"
+            );
+            match session.finish().unwrap_err() {
+                CalibrationError::NotCalibrated => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+        assert!(sensor.transport().commands().is_empty());
+    }
+
+    #[test]
+    fn fails_when_reading_does_not_settle() {
+        let probe = ProbeModel {
+            drift_mv: 2.0,
+            ..ProbeModel::default()
+        };
+        let mut sensor = PhSensor::new(Simulator::new(probe), DEFAULT_ADDRESS);
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            session.set_stability(Stability {
+                timeout: 18_000,
+                ..Stability::default()
+            });
+            match session.calibrate(CalibrationPoint::Mid, 7.0).unwrap_err() {
+                CalibrationError::Unstable { readings, elapsed } => {
+                    assert_eq!(readings, 20);
+                    assert_eq!(elapsed, Duration::from_millis(18_000));
+                }
+                e => panic!("unexpected error: {}", e),
+            }
+            assert_eq!(session.next_point(), Some(CalibrationPoint::Mid));
+        }
+        assert_eq!(sensor.transport().elapsed(), 18_000);
+    }
+
+    #[test]
+    fn checks_that_point_was_stored() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("7.001")
+            .queue_data("7.000")
+            .queue_data("7.002")
+            .queue_data("7.001")
+            .queue_data("7.002")
+            .queue_ack()
+            .queue_data("?CAL,0");
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        {
+            let mut session = CalibrationSession::new(&mut sensor);
+            let err = session.calibrate(CalibrationPoint::Mid, 7.0).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "mid point was not calibrated, calibration status is none"
+            );
+        }
+        assert_eq!(
+            sensor.transport().commands(),
+            vec!["R", "R", "R", "R", "R", "CAL,MID,7.00", "CAL,?"]
+        );
+    }
+}
@@ -1,13 +1,22 @@
 //! I2C Commands for pH EZO Chip.
+use std::cmp;
+use std::fmt;
 use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 
-use super::response::{CalibrationStatus, CompensationValue, ProbeSlope, SensorReading};
+use super::response::{
+    is_valid_name, CalibrationStatus, CompensationValue, DeviceInfo, DeviceName, DeviceStatus,
+    Exported, ExportedInfo, LedStatus, PhResponse, PhScale, ProbeSlope, ProtocolLockStatus,
+    SensorReading,
+};
+use super::transport::{decode_response, EzoTransport, Polling};
 use super::{ErrorKind, EzoError};
 
 use ezo_common::response::ResponseStatus;
-use ezo_common::{response_code, string_from_response_data, write_to_ezo, ResponseCode};
+use ezo_common::{
+    response_code, string_from_response_data, write_to_ezo, BpsRate, ResponseCode,
+};
 
 use failure::ResultExt;
 
@@ -20,11 +29,126 @@ pub const MAX_DATA: usize = 401;
 /// I2C command for the EZO chip.
 pub use ezo_common::{command::*, Command};
 
+/// Commands that can be run over any `EzoTransport`, and not only over a
+/// `LinuxI2CDevice`.
+pub trait TransportCommand: Command<Error = EzoError> {
+    /// Parses the ASCII data sent by the device in reply to this command.
+    fn parse_response(&self, response: &str) -> Result<Self::Response, EzoError>;
+
+    /// Whether the device replies to this command at all. Commands that
+    /// put the chip to sleep, or reset it, do not.
+    fn expects_response(&self) -> bool {
+        true
+    }
+
+    /// Writes the command to the transport, waits for the command's delay,
+    /// and parses the device's response.
+    fn run_on<T: EzoTransport>(&self, transport: &mut T) -> Result<Self::Response, EzoError> {
+        let resp = self.exchange_on(transport)?;
+        self.parse_response(&resp)
+    }
+
+    /// Writes the command to the transport, waits for the command's delay,
+    /// and returns the device's response without parsing it. The response
+    /// is empty for commands that the device does not reply to.
+    fn exchange_on<T: EzoTransport>(&self, transport: &mut T) -> Result<String, EzoError> {
+        transport.write_command(&self.get_command_string())?;
+        if !self.expects_response() {
+            return Ok(String::new());
+        }
+        transport.wait(self.get_delay());
+        let mut data_buffer = [0u8; MAX_DATA];
+        transport.read_response(&mut data_buffer)?;
+        decode_response(&data_buffer)
+    }
+
+    /// Writes the command to the transport, then polls for the response
+    /// until it is ready, instead of waiting for the command's delay.
+    /// Fails with `ErrorKind::PendingResponse` if the response is not
+    /// ready by the polling deadline. An interval of 0 is taken as 1 ms.
+    fn run_polling<T: EzoTransport>(
+        &self,
+        transport: &mut T,
+        polling: &Polling,
+    ) -> Result<Self::Response, EzoError> {
+        transport.write_command(&self.get_command_string())?;
+        if !self.expects_response() {
+            return self.parse_response("");
+        }
+        let interval = cmp::max(polling.interval, 1);
+        let mut waited = 0;
+        loop {
+            transport.wait(interval);
+            waited += interval;
+            let mut data_buffer = [0u8; MAX_DATA];
+            transport.read_response(&mut data_buffer)?;
+            match decode_response(&data_buffer) {
+                Err(ref e) if e.kind() == ErrorKind::PendingResponse && waited < polling.deadline => {
+                    continue
+                }
+                resp => return self.parse_response(&resp?),
+            }
+        }
+    }
+}
+
+/// Implements `TransportCommand` for commands that are defined with
+/// `define_command!`, using the same kind of response.
+macro_rules! impl_transport_command {
+    ($name:ident, NoResponse) => {
+        impl TransportCommand for $name {
+            fn parse_response(&self, _response: &str) -> Result<(), EzoError> {
+                Ok(())
+            }
+
+            fn expects_response(&self) -> bool {
+                false
+            }
+        }
+    };
+    ($name:ident, Ack) => {
+        impl TransportCommand for $name {
+            fn parse_response(&self, _response: &str) -> Result<ResponseStatus, EzoError> {
+                Ok(ResponseStatus::Ack)
+            }
+        }
+    };
+    ($name:ident, $resp:ident : $response:ty, $parse:block) => {
+        impl TransportCommand for $name {
+            fn parse_response(&self, $resp: &str) -> Result<$response, EzoError> {
+                $parse
+            }
+        }
+    };
+}
+
+impl_transport_command!(Baud, NoResponse);
+impl_transport_command!(CalibrationClear, Ack);
+impl_transport_command!(DeviceAddress, NoResponse);
+impl_transport_command!(DeviceInformation, resp: DeviceInfo, { DeviceInfo::parse(resp) });
+impl_transport_command!(Export, resp: Exported, { Exported::parse(resp) });
+impl_transport_command!(ExportInfo, resp: ExportedInfo, { ExportedInfo::parse(resp) });
+impl_transport_command!(Import, Ack);
+impl_transport_command!(Factory, NoResponse);
+impl_transport_command!(Find, Ack);
+impl_transport_command!(LedOn, Ack);
+impl_transport_command!(LedOff, Ack);
+impl_transport_command!(LedState, resp: LedStatus, { LedStatus::parse(resp) });
+impl_transport_command!(ProtocolLockEnable, Ack);
+impl_transport_command!(ProtocolLockDisable, Ack);
+impl_transport_command!(ProtocolLockState, resp: ProtocolLockStatus, {
+    ProtocolLockStatus::parse(resp)
+});
+impl_transport_command!(Sleep, NoResponse);
+impl_transport_command!(Status, resp: DeviceStatus, { DeviceStatus::parse(resp) });
+
 define_command! {
     doc: "`CAL,MID,t` command, where `t` is of type `f64`.",
     cmd: CalibrationMid(f64), { format!("CAL,MID,{:.*}", 2, cmd) }, 900, Ack
 }
 
+impl_transport_command!(CalibrationMid, Ack);
+
 impl FromStr for CalibrationMid {
     type Err = EzoError;
 
@@ -52,6 +176,8 @@ define_command! {
     cmd: CalibrationLow(f64), { format!("CAL,LOW,{:.*}", 2, cmd) }, 900, Ack
 }
 
+impl_transport_command!(CalibrationLow, Ack);
+
 impl FromStr for CalibrationLow {
     type Err = EzoError;
 
@@ -79,6 +205,8 @@ define_command! {
     cmd: CalibrationHigh(f64), { format!("CAL,HIGH,{:.*}", 2, cmd) }, 900, Ack
 }
 
+impl_transport_command!(CalibrationHigh, Ack);
+
 impl FromStr for CalibrationHigh {
     type Err = EzoError;
 
@@ -107,6 +235,10 @@ define_command! {
     resp: CalibrationStatus, { CalibrationStatus::parse(&resp) }
 }
 
+impl_transport_command!(CalibrationState, resp: CalibrationStatus, {
+    CalibrationStatus::parse(resp)
+});
+
 impl FromStr for CalibrationState {
     type Err = EzoError;
 
@@ -125,6 +257,8 @@ define_command! {
     resp: SensorReading, { SensorReading::parse(&resp) }
 }
 
+impl_transport_command!(Reading, resp: SensorReading, { SensorReading::parse(resp) });
+
 impl FromStr for Reading {
     type Err = EzoError;
 
@@ -137,12 +271,149 @@ impl FromStr for Reading {
     }
 }
 
+/// `RT,t` command, where `t` is of type `f64`. Sets the temperature
+/// compensation and returns a `SensorReading` response, within the limits
+/// of the extended pH scale, since the device may be using either scale.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadingWithTemperature(pub f64);
+
+impl Command for ReadingWithTemperature {
+    type Error = EzoError;
+    type Response = SensorReading;
+
+    fn get_command_string(&self) -> String {
+        format!("RT,{:.*}", 3, self.0)
+    }
+
+    fn get_delay(&self) -> u64 {
+        900
+    }
+
+    fn run(&self, dev: &mut LinuxI2CDevice) -> Result<SensorReading, EzoError> {
+        self.run_on(dev)
+    }
+}
+
+impl_transport_command!(ReadingWithTemperature, resp: SensorReading, {
+    SensorReading::parse_with_scale(resp, PhScale::Extended)
+});
+
+impl FromStr for ReadingWithTemperature {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        if supper.starts_with("RT,") {
+            let rest = supper.get(3..).unwrap();
+            let mut split = rest.split(',');
+            let value = match split.next() {
+                Some(n) => n.parse::<f64>().context(ErrorKind::CommandParse)?,
+                _ => Err(ErrorKind::CommandParse)?,
+            };
+            match split.next() {
+                None => Ok(ReadingWithTemperature(value)),
+                _ => Err(ErrorKind::CommandParse)?,
+            }
+        } else {
+            Err(ErrorKind::CommandParse)?
+        }
+    }
+}
+
+/// `R` command, for a device using the given `PhScale`. Returns a
+/// `SensorReading` response, which may lie outside the standard pH scale.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaledReading(pub PhScale);
+
+impl Command for ScaledReading {
+    type Error = EzoError;
+    type Response = SensorReading;
+
+    fn get_command_string(&self) -> String {
+        "R".to_string()
+    }
+
+    fn get_delay(&self) -> u64 {
+        900
+    }
+
+    fn run(&self, dev: &mut LinuxI2CDevice) -> Result<SensorReading, EzoError> {
+        self.run_on(dev)
+    }
+}
+
+impl TransportCommand for ScaledReading {
+    fn parse_response(&self, response: &str) -> Result<SensorReading, EzoError> {
+        SensorReading::parse_with_scale(response, self.0)
+    }
+}
+
+define_command! {
+    doc: "`pHext,1` command. Enables the extended pH scale.",
+    ExtendedScaleOn, { "pHext,1".to_string() }, 300, Ack
+}
+
+impl_transport_command!(ExtendedScaleOn, Ack);
+
+impl FromStr for ExtendedScaleOn {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        match supper.as_ref() {
+            "PHEXT,1" => Ok(ExtendedScaleOn),
+            _ => Err(ErrorKind::CommandParse)?,
+        }
+    }
+}
+
+define_command! {
+    doc: "`pHext,0` command. Disables the extended pH scale.",
+    ExtendedScaleOff, { "pHext,0".to_string() }, 300, Ack
+}
+
+impl_transport_command!(ExtendedScaleOff, Ack);
+
+impl FromStr for ExtendedScaleOff {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        match supper.as_ref() {
+            "PHEXT,0" => Ok(ExtendedScaleOff),
+            _ => Err(ErrorKind::CommandParse)?,
+        }
+    }
+}
+
+define_command! {
+    doc: "`pHext,?` command. Returns a `PhScale` response.",
+    ExtendedScaleState, { "pHext,?".to_string() }, 300,
+    resp: PhScale, { PhScale::parse(&resp) }
+}
+
+impl_transport_command!(ExtendedScaleState, resp: PhScale, { PhScale::parse(resp) });
+
+impl FromStr for ExtendedScaleState {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        match supper.as_ref() {
+            "PHEXT,?" => Ok(ExtendedScaleState),
+            _ => Err(ErrorKind::CommandParse)?,
+        }
+    }
+}
+
 define_command! {
     doc: "`SLOPE,?` command. Returns a `ProbeSlope` response.",
     Slope, { "SLOPE,?".to_string() }, 300,
     resp: ProbeSlope, { ProbeSlope::parse(&resp) }
 }
 
+impl_transport_command!(Slope, resp: ProbeSlope, { ProbeSlope::parse(resp) });
+
 impl FromStr for Slope {
     type Err = EzoError;
 
@@ -160,6 +431,8 @@ define_command! {
     cmd: TemperatureCompensation(f64), { format!("T,{:.*}", 3, cmd) }, 300, Ack
 }
 
+impl_transport_command!(TemperatureCompensation, Ack);
+
 impl FromStr for TemperatureCompensation {
     type Err = EzoError;
 
@@ -188,6 +461,10 @@ define_command! {
     resp: CompensationValue, { CompensationValue::parse(&resp) }
 }
 
+impl_transport_command!(CompensatedTemperatureValue, resp: CompensationValue, {
+    CompensationValue::parse(resp)
+});
+
 impl FromStr for CompensatedTemperatureValue {
     type Err = EzoError;
 
@@ -200,9 +477,355 @@ impl FromStr for CompensatedTemperatureValue {
     }
 }
 
+/// `NAME,n` command, where `n` is of type `String`. An empty name clears
+/// the stored name. Created with `Name::new`, or parsed, so that the name
+/// is always valid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Name(String);
+
+impl Command for Name {
+    type Error = EzoError;
+    type Response = ResponseStatus;
+
+    fn get_command_string(&self) -> String {
+        format!("Name,{}", self.0)
+    }
+
+    fn get_delay(&self) -> u64 {
+        300
+    }
+
+    fn run(&self, dev: &mut LinuxI2CDevice) -> Result<ResponseStatus, EzoError> {
+        self.run_on(dev)
+    }
+}
+
+impl_transport_command!(Name, Ack);
+
+impl Name {
+    /// Creates the command, if `name` is up to 16 printable ASCII
+    /// characters, excluding spaces and commas.
+    pub fn new(name: &str) -> Result<Name, EzoError> {
+        if is_valid_name(name) {
+            Ok(Name(name.to_string()))
+        } else {
+            Err(ErrorKind::CommandParse)?
+        }
+    }
+
+    /// The name to store.
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Name {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        if supper.starts_with("NAME,") && supper != "NAME,?" {
+            // The name keeps its case.
+            Name::new(s.get(5..).unwrap())
+        } else {
+            Err(ErrorKind::CommandParse)?
+        }
+    }
+}
+
+define_command! {
+    doc: "`NAME,?` command. Returns a `DeviceName` response.",
+    NameState, { "Name,?".to_string() }, 300,
+    resp: DeviceName, { DeviceName::parse(&resp) }
+}
+
+impl_transport_command!(NameState, resp: DeviceName, { DeviceName::parse(resp) });
+
+impl FromStr for NameState {
+    type Err = EzoError;
+
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        match supper.as_ref() {
+            "NAME,?" => Ok(NameState),
+            _ => Err(ErrorKind::CommandParse)?,
+        }
+    }
+}
+
+/// Generates the `PhCommand` enum, and its `Command` and
+/// `TransportCommand` implementations, from its variants and the kind of
+/// response that each one returns.
+macro_rules! define_ph_command {
+    ($($variant:ident => $response:ident),* $(,)*) => {
+        /// Any command that can be issued to the PH EZO Chip.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum PhCommand {
+            $($variant($variant),)*
+        }
+
+        impl Command for PhCommand {
+            type Error = EzoError;
+            type Response = PhResponse;
+
+            fn get_command_string(&self) -> String {
+                match *self {
+                    $(PhCommand::$variant(ref cmd) => cmd.get_command_string(),)*
+                }
+            }
+
+            fn get_delay(&self) -> u64 {
+                match *self {
+                    $(PhCommand::$variant(ref cmd) => cmd.get_delay(),)*
+                }
+            }
+
+            fn run(&self, dev: &mut LinuxI2CDevice) -> Result<PhResponse, EzoError> {
+                self.run_on(dev)
+            }
+        }
+
+        impl PhCommand {
+            /// Name of the type of response that the command returns.
+            pub fn response_type(&self) -> &'static str {
+                match *self {
+                    $(PhCommand::$variant(_) => stringify!($response),)*
+                }
+            }
+        }
+
+        impl TransportCommand for PhCommand {
+            fn parse_response(&self, response: &str) -> Result<PhResponse, EzoError> {
+                match *self {
+                    $(PhCommand::$variant(ref cmd) => {
+                        let _resp = cmd.parse_response(response)?;
+                        Ok(define_ph_command!(@response $response, _resp))
+                    })*
+                }
+            }
+
+            fn expects_response(&self) -> bool {
+                match *self {
+                    $(PhCommand::$variant(ref cmd) => cmd.expects_response(),)*
+                }
+            }
+        }
+
+        $(
+            impl From<$variant> for PhCommand {
+                fn from(cmd: $variant) -> PhCommand {
+                    PhCommand::$variant(cmd)
+                }
+            }
+        )*
+    };
+    (@response Ack, $resp:ident) => { PhResponse::Ack };
+    (@response NoResponse, $resp:ident) => { PhResponse::NoResponse };
+    (@response $response:ident, $resp:ident) => { PhResponse::$response($resp) };
+}
+
+define_ph_command! {
+    CalibrationMid => Ack,
+    CalibrationLow => Ack,
+    CalibrationHigh => Ack,
+    CalibrationState => CalibrationStatus,
+    CalibrationClear => Ack,
+    Reading => SensorReading,
+    ReadingWithTemperature => SensorReading,
+    ExtendedScaleOn => Ack,
+    ExtendedScaleOff => Ack,
+    ExtendedScaleState => PhScale,
+    Slope => ProbeSlope,
+    TemperatureCompensation => Ack,
+    CompensatedTemperatureValue => CompensationValue,
+    Name => Ack,
+    NameState => DeviceName,
+    DeviceAddress => NoResponse,
+    Baud => NoResponse,
+    DeviceInformation => DeviceInfo,
+    Export => Exported,
+    ExportInfo => ExportedInfo,
+    Import => Ack,
+    Factory => NoResponse,
+    Find => Ack,
+    LedOn => Ack,
+    LedOff => Ack,
+    LedState => LedStatus,
+    ProtocolLockEnable => Ack,
+    ProtocolLockDisable => Ack,
+    ProtocolLockState => ProtocolLockStatus,
+    Sleep => NoResponse,
+    Status => DeviceStatus,
+}
+
+impl FromStr for PhCommand {
+    type Err = EzoError;
+
+    /// Parses any command string, regardless of case.
+    fn from_str(s: &str) -> Result<Self, EzoError> {
+        let supper = s.to_uppercase();
+        let head = supper.split(',').next().unwrap();
+        let cmd = match (head, supper.as_ref()) {
+            ("R", _) => Reading::from_str(s)?.into(),
+            ("RT", _) => ReadingWithTemperature::from_str(s)?.into(),
+            (_, "CAL,?") => CalibrationState.into(),
+            (_, "CAL,CLEAR") => CalibrationClear.into(),
+            ("CAL", c) if c.starts_with("CAL,MID,") => CalibrationMid::from_str(s)?.into(),
+            ("CAL", c) if c.starts_with("CAL,LOW,") => CalibrationLow::from_str(s)?.into(),
+            ("CAL", c) if c.starts_with("CAL,HIGH,") => CalibrationHigh::from_str(s)?.into(),
+            ("PHEXT", _) => match supper.as_ref() {
+                "PHEXT,1" => ExtendedScaleOn.into(),
+                "PHEXT,0" => ExtendedScaleOff.into(),
+                _ => ExtendedScaleState::from_str(s)?.into(),
+            },
+            ("SLOPE", _) => Slope::from_str(s)?.into(),
+            (_, "T,?") => CompensatedTemperatureValue.into(),
+            ("T", _) => TemperatureCompensation::from_str(s)?.into(),
+            (_, "NAME,?") => NameState.into(),
+            ("NAME", _) => Name::from_str(s)?.into(),
+            ("I2C", _) => {
+                let address = supper.get(4..).unwrap_or("");
+                let address = address.parse::<u16>().context(ErrorKind::CommandParse)?;
+                DeviceAddress(address).into()
+            }
+            ("BAUD", _) => Baud(parse_bps_rate(supper.get(5..).unwrap_or(""))?).into(),
+            (_, "I") => DeviceInformation.into(),
+            (_, "EXPORT") => Export.into(),
+            (_, "EXPORT,?") => ExportInfo.into(),
+            ("IMPORT", _) if s.len() > 7 => Import(s.get(7..).unwrap().to_string()).into(),
+            (_, "FACTORY") => Factory.into(),
+            (_, "F") => Find.into(),
+            (_, "L,1") => LedOn.into(),
+            (_, "L,0") => LedOff.into(),
+            (_, "L,?") => LedState.into(),
+            (_, "PLOCK,1") => ProtocolLockEnable.into(),
+            (_, "PLOCK,0") => ProtocolLockDisable.into(),
+            (_, "PLOCK,?") => ProtocolLockState.into(),
+            (_, "SLEEP") => Sleep.into(),
+            (_, "STATUS") => Status.into(),
+            _ => return Err(ErrorKind::CommandParse)?,
+        };
+        Ok(cmd)
+    }
+}
+
+/// Parses the rate of a `BAUD,n` command.
+fn parse_bps_rate(s: &str) -> Result<BpsRate, EzoError> {
+    let rate = match s.parse::<u32>().context(ErrorKind::CommandParse)? {
+        300 => BpsRate::Bp300,
+        1200 => BpsRate::Bp1200,
+        2400 => BpsRate::Bp2400,
+        9600 => BpsRate::Bp9600,
+        19200 => BpsRate::Bp19200,
+        38400 => BpsRate::Bp38400,
+        57600 => BpsRate::Bp57600,
+        115200 => BpsRate::Bp115200,
+        _ => return Err(ErrorKind::CommandParse)?,
+    };
+    Ok(rate)
+}
+
+impl fmt::Display for PhCommand {
+    /// Writes the command string, as it is sent to the device.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_command_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mock::MockDevice;
+
+    #[test]
+    fn run_command_on_transport() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("7.012")
+            .queue_ack()
+            .queue_data("?SLOPE,99.7,100.3");
+
+        let reading = Reading.run_on(&mut dev).unwrap();
+        assert_eq!(reading, SensorReading(7.012));
+
+        let ack = CalibrationMid(7.0).run_on(&mut dev).unwrap();
+        assert_eq!(ack, ResponseStatus::Ack);
+
+        let slope = Slope.run_on(&mut dev).unwrap();
+        assert_eq!(slope.acid_end, 99.7);
+
+        assert_eq!(dev.commands(), vec!["R", "CAL,MID,7.00", "SLOPE,?"]);
+        assert_eq!(dev.waits(), vec![900, 900, 300]);
+    }
+
+    #[test]
+    fn run_command_without_response_on_transport() {
+        let mut dev = MockDevice::new();
+        Sleep.run_on(&mut dev).unwrap();
+        assert_eq!(dev.commands(), vec!["SLEEP"]);
+        assert!(dev.waits().is_empty());
+    }
+
+    #[test]
+    fn exchange_command_on_transport() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("?SLOPE,99.7,100.3");
+        assert_eq!(Slope.exchange_on(&mut dev).unwrap(), "?SLOPE,99.7,100.3");
+        assert_eq!(Sleep.exchange_on(&mut dev).unwrap(), "");
+        assert_eq!(dev.commands(), vec!["SLOPE,?", "SLEEP"]);
+    }
+
+    #[test]
+    fn run_command_polling_on_transport() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(254).queue_code(254).queue_data("7.012");
+
+        let polling = Polling {
+            interval: 100,
+            deadline: 1_000,
+        };
+        let reading = Reading.run_polling(&mut dev, &polling).unwrap();
+        assert_eq!(reading, SensorReading(7.012));
+        assert_eq!(dev.commands(), vec!["R"]);
+        assert_eq!(dev.waits(), vec![100, 100, 100]);
+    }
+
+    #[test]
+    fn run_command_polling_past_deadline_yields_err() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(254).queue_code(254).queue_code(254);
+
+        let polling = Polling {
+            interval: 100,
+            deadline: 200,
+        };
+        let err = Reading.run_polling(&mut dev, &polling).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+        assert_eq!(dev.waits(), vec![100, 100]);
+    }
+
+    #[test]
+    fn run_command_polling_without_interval_still_reaches_deadline() {
+        let mut dev = MockDevice::new();
+        for _ in 0..5 {
+            dev.queue_code(254);
+        }
+
+        let polling = Polling {
+            interval: 0,
+            deadline: 3,
+        };
+        let err = Reading.run_polling(&mut dev, &polling).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+        assert_eq!(dev.waits(), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn run_command_on_transport_with_error_code_yields_err() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(2);
+        let err = Reading.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+    }
 
     #[test]
     fn build_command_calibration_mid() {
@@ -311,6 +934,113 @@ mod tests {
         assert_eq!(cmd, Reading);
     }
 
+    #[test]
+    fn build_command_reading_with_temperature() {
+        let cmd = ReadingWithTemperature(19.5);
+        assert_eq!(cmd.get_command_string(), "RT,19.500");
+        assert_eq!(cmd.get_delay(), 900);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_reading_with_temperature() {
+        let cmd = "rt,0".parse::<ReadingWithTemperature>().unwrap();
+        assert_eq!(cmd, ReadingWithTemperature(0_f64));
+
+        let cmd = "Rt,25.5".parse::<ReadingWithTemperature>().unwrap();
+        assert_eq!(cmd, ReadingWithTemperature(25.5));
+    }
+
+    #[test]
+    fn parse_invalid_command_reading_with_temperature_yields_err() {
+        let cmd = "RT,".parse::<ReadingWithTemperature>();
+        assert!(cmd.is_err());
+
+        let cmd = "RT,1a21.43".parse::<ReadingWithTemperature>();
+        assert!(cmd.is_err());
+
+        let cmd = "RT,25,3".parse::<ReadingWithTemperature>();
+        assert!(cmd.is_err());
+    }
+
+    #[test]
+    fn run_reading_with_temperature_on_either_scale() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("7.012").queue_data("14.500").queue_data("16.000");
+
+        let cmd = ReadingWithTemperature(19.5);
+        assert_eq!(cmd.run_on(&mut dev).unwrap(), SensorReading(7.012));
+        assert_eq!(cmd.run_on(&mut dev).unwrap(), SensorReading(14.5));
+        let err = cmd.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidReading);
+    }
+
+    #[test]
+    fn build_command_scaled_reading() {
+        let cmd = ScaledReading(PhScale::Extended);
+        assert_eq!(cmd.get_command_string(), "R");
+        assert_eq!(cmd.get_delay(), 900);
+    }
+
+    #[test]
+    fn run_scaled_reading_on_transport() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("-1.2").queue_data("-1.2");
+
+        let reading = ScaledReading(PhScale::Extended).run_on(&mut dev).unwrap();
+        assert_eq!(reading, SensorReading(-1.2));
+
+        let err = Reading.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidReading);
+    }
+
+    #[test]
+    fn build_command_extended_scale_on() {
+        let cmd = ExtendedScaleOn;
+        assert_eq!(cmd.get_command_string(), "pHext,1");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_extended_scale_on() {
+        let cmd = "phext,1".parse::<ExtendedScaleOn>().unwrap();
+        assert_eq!(cmd, ExtendedScaleOn);
+
+        let cmd = "pHEXT,1".parse::<ExtendedScaleOn>().unwrap();
+        assert_eq!(cmd, ExtendedScaleOn);
+    }
+
+    #[test]
+    fn build_command_extended_scale_off() {
+        let cmd = ExtendedScaleOff;
+        assert_eq!(cmd.get_command_string(), "pHext,0");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_extended_scale_off() {
+        let cmd = "phext,0".parse::<ExtendedScaleOff>().unwrap();
+        assert_eq!(cmd, ExtendedScaleOff);
+
+        let cmd = "PHEXT,0".parse::<ExtendedScaleOff>().unwrap();
+        assert_eq!(cmd, ExtendedScaleOff);
+    }
+
+    #[test]
+    fn build_command_extended_scale_state() {
+        let cmd = ExtendedScaleState;
+        assert_eq!(cmd.get_command_string(), "pHext,?");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_extended_scale_state() {
+        let cmd = "phext,?".parse::<ExtendedScaleState>().unwrap();
+        assert_eq!(cmd, ExtendedScaleState);
+
+        let cmd = "pHext,?".parse::<ExtendedScaleState>().unwrap();
+        assert_eq!(cmd, ExtendedScaleState);
+    }
+
     #[test]
     fn build_command_slope() {
         let cmd = Slope;
@@ -363,4 +1093,191 @@ mod tests {
         let cmd = "T,?".parse::<CompensatedTemperatureValue>().unwrap();
         assert_eq!(cmd, CompensatedTemperatureValue);
     }
+
+    #[test]
+    fn build_command_name() {
+        let cmd = Name::new("Tank-1").unwrap();
+        assert_eq!(cmd.get_command_string(), "Name,Tank-1");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_name() {
+        let cmd = "name,Tank-1".parse::<Name>().unwrap();
+        assert_eq!(cmd.name(), "Tank-1");
+
+        let cmd = "NAME,".parse::<Name>().unwrap();
+        assert_eq!(cmd.name(), "");
+    }
+
+    #[test]
+    fn parse_invalid_command_name_yields_err() {
+        let cmd = "name".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,?".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,tank 1".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,tank,1".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,12345678901234567".parse::<Name>();
+        assert!(cmd.is_err());
+
+        assert!(Name::new("tanque-número-1").is_err());
+    }
+
+    #[test]
+    fn build_command_name_state() {
+        let cmd = NameState;
+        assert_eq!(cmd.get_command_string(), "Name,?");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_name_state() {
+        let cmd = "name,?".parse::<NameState>().unwrap();
+        assert_eq!(cmd, NameState);
+
+        let cmd = "NAME,?".parse::<NameState>().unwrap();
+        assert_eq!(cmd, NameState);
+    }
+
+    #[test]
+    fn parse_case_insensitive_ph_command() {
+        let cmd = "cal,mid,7".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::CalibrationMid(CalibrationMid(7.0)));
+
+        let cmd = "t,?".parse::<PhCommand>().unwrap();
+        assert_eq!(
+            cmd,
+            PhCommand::CompensatedTemperatureValue(CompensatedTemperatureValue)
+        );
+
+        let cmd = "T,19.5".parse::<PhCommand>().unwrap();
+        assert_eq!(
+            cmd,
+            PhCommand::TemperatureCompensation(TemperatureCompensation(19.5))
+        );
+
+        let cmd = "r".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::Reading(Reading));
+
+        let cmd = "Cal,Clear".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::CalibrationClear(CalibrationClear));
+
+        let cmd = "name,Tank-1".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::Name(Name::new("Tank-1").unwrap()));
+
+        let cmd = "i2c,100".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::DeviceAddress(DeviceAddress(100)));
+
+        let cmd = "baud,9600".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::Baud(Baud(BpsRate::Bp9600)));
+        assert_eq!(cmd.response_type(), "NoResponse");
+
+        let cmd = "plock,?".parse::<PhCommand>().unwrap();
+        assert_eq!(cmd, PhCommand::ProtocolLockState(ProtocolLockState));
+    }
+
+    #[test]
+    fn parse_invalid_ph_command_yields_err() {
+        let cmd = "".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "cal,mid,".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "cal,foo".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "r,1".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "i2c,foo".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "baud,4800".parse::<PhCommand>();
+        assert!(cmd.is_err());
+
+        let cmd = "baud,fast".parse::<PhCommand>();
+        assert!(cmd.is_err());
+    }
+
+    #[test]
+    fn display_ph_command_as_command_string() {
+        let inputs = [
+            "CAL,MID,7.00",
+            "CAL,LOW,4.00",
+            "CAL,HIGH,10.00",
+            "CAL,?",
+            "CAL,CLEAR",
+            "R",
+            "RT,25.000",
+            "pHext,1",
+            "pHext,0",
+            "pHext,?",
+            "SLOPE,?",
+            "T,25.000",
+            "T,?",
+            "Name,Tank-1",
+            "Name,?",
+            "I",
+            "EXPORT",
+            "EXPORT,?",
+            "F",
+            "L,1",
+            "L,0",
+            "L,?",
+            "PLOCK,1",
+            "PLOCK,0",
+            "PLOCK,?",
+            "SLEEP",
+            "STATUS",
+            "BAUD,115200",
+        ];
+        for input in inputs.iter() {
+            let cmd = input.parse::<PhCommand>().unwrap();
+            assert_eq!(&cmd.to_string(), input);
+            assert_eq!(cmd.get_command_string(), cmd.to_string());
+        }
+    }
+
+    #[test]
+    fn run_ph_command_on_transport() {
+        let mut dev = MockDevice::new();
+        dev.queue_data("7.012").queue_ack().queue_data("?CAL,1");
+
+        let cmd: PhCommand = "r".parse().unwrap();
+        let resp = cmd.run_on(&mut dev).unwrap();
+        assert_eq!(resp, PhResponse::SensorReading(SensorReading(7.012)));
+
+        let cmd: PhCommand = "cal,mid,7".parse().unwrap();
+        assert_eq!(cmd.get_delay(), 900);
+        assert_eq!(cmd.run_on(&mut dev).unwrap(), PhResponse::Ack);
+
+        let cmd: PhCommand = CalibrationState.into();
+        assert_eq!(
+            cmd.run_on(&mut dev).unwrap(),
+            PhResponse::CalibrationStatus(CalibrationStatus::OnePoint)
+        );
+
+        let cmd: PhCommand = "sleep".parse().unwrap();
+        assert_eq!(cmd.run_on(&mut dev).unwrap(), PhResponse::NoResponse);
+
+        assert_eq!(dev.commands(), vec!["R", "CAL,MID,7.00", "CAL,?", "SLEEP"]);
+    }
+
+    #[test]
+    fn ph_command_response_type() {
+        let cmd: PhCommand = "slope,?".parse().unwrap();
+        assert_eq!(cmd.response_type(), "ProbeSlope");
+        let cmd: PhCommand = "t,19.5".parse().unwrap();
+        assert_eq!(cmd.response_type(), "Ack");
+        let cmd: PhCommand = "sleep".parse().unwrap();
+        assert_eq!(cmd.response_type(), "NoResponse");
+    }
 }
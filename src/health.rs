@@ -0,0 +1,211 @@
+//! Assessment of the pH probe's health, from its slope.
+//!
+//! A healthy probe has slopes close to 100 % of the ideal, on both sides
+//! of the mid point, and a potential close to 0 mV at pH 7. Both drift as
+//! the electrode ages, until it has to be replaced.
+use std::fmt;
+
+use super::response::ProbeSlope;
+
+/// Condition of the probe, from best to worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProbeCondition {
+    Good,
+    Aging,
+    Replace,
+}
+
+impl fmt::Display for ProbeCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProbeCondition::Good => write!(f, "good"),
+            ProbeCondition::Aging => write!(f, "aging"),
+            ProbeCondition::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+/// Limits beyond which the probe is considered aging, or to be replaced.
+/// Slopes are compared by how far they are from 100 %, and the zero
+/// offset by its absolute value in mV.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HealthThresholds {
+    pub aging_slope_deviation: f64,
+    pub replace_slope_deviation: f64,
+    pub aging_offset_mv: f64,
+    pub replace_offset_mv: f64,
+}
+
+impl Default for HealthThresholds {
+    /// Slopes within 5 % of the ideal and an offset within 30 mV are good.
+    /// Slopes beyond 15 %, or an offset beyond 60 mV, call for a new probe.
+    fn default() -> HealthThresholds {
+        HealthThresholds {
+            aging_slope_deviation: 5.0,
+            replace_slope_deviation: 15.0,
+            aging_offset_mv: 30.0,
+            replace_offset_mv: 60.0,
+        }
+    }
+}
+
+impl HealthThresholds {
+    /// Condition of a slope, in percent of the ideal slope.
+    pub fn slope_condition(&self, slope: f64) -> ProbeCondition {
+        classify(
+            (slope - 100.0).abs(),
+            self.aging_slope_deviation,
+            self.replace_slope_deviation,
+        )
+    }
+
+    /// Condition of a zero offset, in mV.
+    pub fn offset_condition(&self, offset_mv: f64) -> ProbeCondition {
+        classify(
+            offset_mv.abs(),
+            self.aging_offset_mv,
+            self.replace_offset_mv,
+        )
+    }
+}
+
+fn classify(deviation: f64, aging: f64, replace: f64) -> ProbeCondition {
+    if deviation > replace {
+        ProbeCondition::Replace
+    } else if deviation > aging {
+        ProbeCondition::Aging
+    } else {
+        ProbeCondition::Good
+    }
+}
+
+/// Health of the probe, overall and for each part of its slope.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProbeHealth {
+    /// Worst of the conditions below.
+    pub condition: ProbeCondition,
+    pub acid_end: ProbeCondition,
+    pub base_end: ProbeCondition,
+    /// Condition of the zero offset, when the device reports it.
+    pub zero_offset: Option<ProbeCondition>,
+    /// The slope that was assessed.
+    pub slope: ProbeSlope,
+}
+
+impl ProbeHealth {
+    /// Assesses the probe from its slope.
+    pub fn assess(slope: &ProbeSlope, thresholds: &HealthThresholds) -> ProbeHealth {
+        let acid_end = thresholds.slope_condition(slope.acid_end);
+        let base_end = thresholds.slope_condition(slope.base_end);
+        let zero_offset = slope
+            .zero_offset_mv
+            .map(|offset| thresholds.offset_condition(offset));
+        let condition = acid_end
+            .max(base_end)
+            .max(zero_offset.unwrap_or(ProbeCondition::Good));
+        ProbeHealth {
+            condition,
+            acid_end,
+            base_end,
+            zero_offset,
+            slope: *slope,
+        }
+    }
+
+    /// A human-readable diagnosis. Only the values that are not good are
+    /// listed, unless the probe is good.
+    pub fn diagnosis(&self) -> String {
+        let verdict = match self.condition {
+            ProbeCondition::Good => "probe is good",
+            ProbeCondition::Aging => "probe is aging, clean and recalibrate it",
+            ProbeCondition::Replace => "probe should be replaced",
+        };
+        let show = |condition: ProbeCondition| {
+            self.condition == ProbeCondition::Good || condition != ProbeCondition::Good
+        };
+        let mut findings = Vec::new();
+        if show(self.acid_end) {
+            findings.push(format!("acid slope is {:.1} %", self.slope.acid_end));
+        }
+        if show(self.base_end) {
+            findings.push(format!("base slope is {:.1} %", self.slope.base_end));
+        }
+        if let (Some(condition), Some(offset)) = (self.zero_offset, self.slope.zero_offset_mv) {
+            if show(condition) {
+                findings.push(format!("zero offset is {:.1} mV", offset));
+            }
+        }
+        format!("{}: {}", verdict, findings.join(", "))
+    }
+}
+
+impl fmt::Display for ProbeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnosis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slope(acid_end: f64, base_end: f64, zero_offset_mv: Option<f64>) -> ProbeSlope {
+        ProbeSlope {
+            acid_end,
+            base_end,
+            zero_offset_mv,
+        }
+    }
+
+    #[test]
+    fn classifies_slopes_and_offset() {
+        let thresholds = HealthThresholds::default();
+        assert_eq!(thresholds.slope_condition(97.0), ProbeCondition::Good);
+        assert_eq!(thresholds.slope_condition(104.0), ProbeCondition::Good);
+        assert_eq!(thresholds.slope_condition(91.0), ProbeCondition::Aging);
+        assert_eq!(thresholds.slope_condition(84.0), ProbeCondition::Replace);
+        assert_eq!(thresholds.offset_condition(-25.0), ProbeCondition::Good);
+        assert_eq!(thresholds.offset_condition(-45.0), ProbeCondition::Aging);
+        assert_eq!(thresholds.offset_condition(61.0), ProbeCondition::Replace);
+    }
+
+    #[test]
+    fn good_probe() {
+        let health = ProbeHealth::assess(&slope(99.7, 98.2, Some(-4.2)), &Default::default());
+        assert_eq!(health.condition, ProbeCondition::Good);
+        assert_eq!(
+            health.to_string(),
+            "probe is good: acid slope is 99.7 %, base slope is 98.2 %, zero offset is -4.2 mV"
+        );
+    }
+
+    #[test]
+    fn worst_condition_wins() {
+        let health = ProbeHealth::assess(&slope(92.0, 98.2, Some(70.0)), &Default::default());
+        assert_eq!(health.acid_end, ProbeCondition::Aging);
+        assert_eq!(health.base_end, ProbeCondition::Good);
+        assert_eq!(health.zero_offset, Some(ProbeCondition::Replace));
+        assert_eq!(health.condition, ProbeCondition::Replace);
+        assert_eq!(
+            health.diagnosis(),
+            "probe should be replaced: acid slope is 92.0 %, zero offset is 70.0 mV"
+        );
+
+        let health = ProbeHealth::assess(&slope(99.0, 91.5, None), &Default::default());
+        assert_eq!(health.zero_offset, None);
+        assert_eq!(
+            health.diagnosis(),
+            "probe is aging, clean and recalibrate it: base slope is 91.5 %"
+        );
+    }
+
+    #[test]
+    fn custom_thresholds() {
+        let thresholds = HealthThresholds {
+            aging_slope_deviation: 2.0,
+            ..HealthThresholds::default()
+        };
+        let health = ProbeHealth::assess(&slope(97.0, 99.0, None), &thresholds);
+        assert_eq!(health.condition, ProbeCondition::Aging);
+    }
+}
@@ -0,0 +1,353 @@
+//! History of the calibrations made through a `PhSensor`.
+//!
+//! Each calibration, clearing or import made through the `PhSensor`
+//! methods appends an entry to a `HistoryStore`. Calibration commands sent
+//! with `PhSensor::run`, or with `Command::run` and
+//! `TransportCommand::run_on`, are not recorded. The entries are kept in
+//! memory with `MemoryHistory`, or appended to a file, one JSON object per
+//! line, with `JsonLinesHistory`.
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use super::calibration::CalibrationPoint;
+use super::response::{CalibrationStatus, ProbeSlope};
+use super::EzoError;
+
+use chrono::{DateTime, Utc};
+
+use failure::Fail;
+
+use serde_json;
+
+/// A calibration, as recorded in the history.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationEntry {
+    /// When the calibration was made.
+    pub timestamp: DateTime<Utc>,
+    /// Point that was calibrated, or `None` if the calibration was cleared
+    /// or imported.
+    pub point: Option<CalibrationPoint>,
+    /// pH of the buffer the point was calibrated to.
+    pub ph: Option<f64>,
+    /// Whether the calibration was imported, as exported from a chip.
+    #[serde(default)]
+    pub imported: bool,
+    /// Temperature used for compensation, in °C.
+    pub temperature: f64,
+    /// Probe slope before the calibration.
+    pub slope_before: ProbeSlope,
+    /// Probe slope after the calibration.
+    pub slope_after: ProbeSlope,
+    /// Calibration status after the calibration.
+    pub status: CalibrationStatus,
+}
+
+/// Which entries to return from the history. Every field that is set must
+/// match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryQuery {
+    /// Entries made at this time, or later.
+    pub since: Option<DateTime<Utc>>,
+    /// Entries made before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Entries for this point, or `Some(None)` for cleared and imported
+    /// calibrations.
+    pub point: Option<Option<CalibrationPoint>>,
+    /// Only the latest entries, up to this many.
+    pub last: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Whether the entry matches the query, regardless of `last`.
+    pub fn matches(&self, entry: &CalibrationEntry) -> bool {
+        let since_ok = match self.since {
+            Some(since) => entry.timestamp >= since,
+            None => true,
+        };
+        let until_ok = match self.until {
+            Some(until) => entry.timestamp < until,
+            None => true,
+        };
+        let point_ok = match self.point {
+            Some(point) => entry.point == point,
+            None => true,
+        };
+        since_ok && until_ok && point_ok
+    }
+}
+
+/// Where calibration entries are stored.
+pub trait HistoryStore: fmt::Debug {
+    /// Appends an entry to the history.
+    fn append(&mut self, entry: &CalibrationEntry) -> Result<(), HistoryError>;
+
+    /// All the entries, oldest first.
+    fn entries(&self) -> Result<Vec<CalibrationEntry>, HistoryError>;
+
+    /// The entries that match the query, oldest first.
+    fn query(&self, query: &HistoryQuery) -> Result<Vec<CalibrationEntry>, HistoryError> {
+        let mut entries: Vec<CalibrationEntry> = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| query.matches(entry))
+            .collect();
+        if let Some(last) = query.last {
+            let skip = entries.len().saturating_sub(last);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
+
+    /// The latest entry, if any.
+    fn latest(&self) -> Result<Option<CalibrationEntry>, HistoryError> {
+        Ok(self.entries()?.pop())
+    }
+}
+
+/// A history kept in memory.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryHistory {
+    entries: Vec<CalibrationEntry>,
+}
+
+impl MemoryHistory {
+    /// Creates an empty history.
+    pub fn new() -> MemoryHistory {
+        MemoryHistory::default()
+    }
+}
+
+impl HistoryStore for MemoryHistory {
+    fn append(&mut self, entry: &CalibrationEntry) -> Result<(), HistoryError> {
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<CalibrationEntry>, HistoryError> {
+        Ok(self.entries.clone())
+    }
+}
+
+/// A history kept in a file, with one JSON object per line. The file is
+/// created on the first entry, and only ever appended to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonLinesHistory {
+    path: PathBuf,
+}
+
+impl JsonLinesHistory {
+    /// Uses the file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> JsonLinesHistory {
+        JsonLinesHistory {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl HistoryStore for JsonLinesHistory {
+    fn append(&mut self, entry: &CalibrationEntry) -> Result<(), HistoryError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<CalibrationEntry>, HistoryError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(entries)
+    }
+}
+
+/// Error returned by a `HistoryStore`.
+#[derive(Debug)]
+pub enum HistoryError {
+    /// The store could not be read or written.
+    Io(io::Error),
+    /// An entry could not be serialized, or deserialized.
+    Json(serde_json::Error),
+    /// The device could not be queried for the entry.
+    Device(EzoError),
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(error: io::Error) -> HistoryError {
+        HistoryError::Io(error)
+    }
+}
+
+impl From<EzoError> for HistoryError {
+    fn from(error: EzoError) -> HistoryError {
+        HistoryError::Device(error)
+    }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(error: serde_json::Error) -> HistoryError {
+        HistoryError::Json(error)
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HistoryError::Io(ref e) => write!(f, "calibration history I/O error: {}", e),
+            HistoryError::Json(ref e) => write!(f, "invalid calibration history entry: {}", e),
+            HistoryError::Device(ref e) => {
+                write!(
+                    f,
+                    "could not query the device for the calibration history: {}",
+                    e
+                )
+            }
+        }
+    }
+}
+
+impl Fail for HistoryError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            HistoryError::Io(ref e) => Some(e),
+            HistoryError::Json(ref e) => Some(e),
+            HistoryError::Device(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use chrono::Duration;
+
+    fn start() -> DateTime<Utc> {
+        "2018-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn entry(minutes: i64, point: Option<CalibrationPoint>) -> CalibrationEntry {
+        let slope = ProbeSlope {
+            acid_end: 100.0,
+            base_end: 100.0,
+            zero_offset_mv: None,
+        };
+        CalibrationEntry {
+            timestamp: start() + Duration::minutes(minutes),
+            point,
+            ph: point.map(|_| 7.0),
+            imported: false,
+            temperature: 25.0,
+            slope_before: slope,
+            slope_after: slope,
+            status: CalibrationStatus::OnePoint,
+        }
+    }
+
+    fn history() -> MemoryHistory {
+        let mut history = MemoryHistory::new();
+        history.append(&entry(0, None)).unwrap();
+        history
+            .append(&entry(10, Some(CalibrationPoint::Mid)))
+            .unwrap();
+        history
+            .append(&entry(20, Some(CalibrationPoint::Low)))
+            .unwrap();
+        history
+            .append(&entry(30, Some(CalibrationPoint::Mid)))
+            .unwrap();
+        history
+    }
+
+    #[test]
+    fn queries_by_time_and_point() {
+        let history = history();
+        let start = start();
+
+        let query = HistoryQuery {
+            since: Some(start + Duration::minutes(10)),
+            until: Some(start + Duration::minutes(30)),
+            ..HistoryQuery::default()
+        };
+        let entries = history.query(&query).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                entry(10, Some(CalibrationPoint::Mid)),
+                entry(20, Some(CalibrationPoint::Low))
+            ]
+        );
+
+        let query = HistoryQuery {
+            point: Some(Some(CalibrationPoint::Mid)),
+            last: Some(1),
+            ..HistoryQuery::default()
+        };
+        let entries = history.query(&query).unwrap();
+        assert_eq!(entries, vec![entry(30, Some(CalibrationPoint::Mid))]);
+
+        let query = HistoryQuery {
+            point: Some(None),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(history.query(&query).unwrap(), vec![entry(0, None)]);
+        assert_eq!(
+            history.latest().unwrap(),
+            Some(entry(30, Some(CalibrationPoint::Mid)))
+        );
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let path = env::temp_dir().join(format!("ezo-ph-history-{}.jsonl", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = JsonLinesHistory::new(&path);
+        assert!(store.entries().unwrap().is_empty());
+        for entry in history().entries().unwrap() {
+            store.append(&entry).unwrap();
+        }
+        assert_eq!(store.entries().unwrap(), history().entries().unwrap());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 4);
+        assert!(contents.starts_with("{\"timestamp\":\"2018-05-01T12:00:00Z\""));
+
+        // Entries recorded before imports were have no `imported` field.
+        let mut value = serde_json::to_value(entry(0, None)).unwrap();
+        value.as_object_mut().unwrap().remove("imported");
+        fs::write(&path, format!("{}\n", value)).unwrap();
+        assert_eq!(store.entries().unwrap(), vec![entry(0, None)]);
+
+        fs::write(&path, "not json\n").unwrap();
+        match store.entries().unwrap_err() {
+            HistoryError::Json(_) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
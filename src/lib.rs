@@ -3,8 +3,19 @@
 //! scale, etc.
 #[macro_use]
 extern crate ezo_common;
+extern crate chrono;
 extern crate failure;
 extern crate i2cdev;
+extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate termios;
+#[cfg(feature = "mqtt")]
+extern crate rumqttc;
+#[cfg(feature = "exporter")]
+extern crate tiny_http;
 
 /// Issuable commands for the PH EZO Chip.
 pub mod command;
@@ -12,5 +23,47 @@ pub mod command;
 /// Parseable responses from the PH EZO Chip.
 pub mod response;
 
+/// Transports for communicating with the PH EZO Chip.
+pub mod transport;
+
+/// UART transport for the PH EZO Chip.
+pub mod uart;
+
+/// Scripted mock of the PH EZO Chip, for tests.
+pub mod mock;
+
+/// Software emulation of the PH EZO Chip and its probe.
+pub mod simulator;
+
+/// High-level driver for the PH EZO Chip.
+pub mod sensor;
+
+/// Retrying of commands that fail with transient errors.
+pub mod retry;
+
+/// Detection of stable pH readings.
+pub mod stability;
+
+/// Calibration buffers and their pH-vs-temperature tables.
+pub mod buffer;
+
+/// Guided calibration of the PH EZO Chip.
+pub mod calibration;
+
+/// Probe health assessment from slope data.
+pub mod health;
+
+/// Backup and restore of the calibration.
+pub mod backup;
+
+/// History of the calibrations.
+pub mod history;
+
+/// Prometheus metrics of the devices.
+pub mod metrics;
+
+/// Publishing over MQTT.
+pub mod mqtt;
+
 // Re-export errors from ezo_common crate.
 pub use ezo_common::errors::{ErrorKind, EzoError};
@@ -0,0 +1,563 @@
+//! Metrics of PH EZO Chips, in the Prometheus text format.
+//!
+//! `DeviceMetrics` holds what was last queried from a device, along with
+//! counters and a latency histogram of its readings. An `Exporter` collects
+//! the metrics of its devices and, with the `exporter` feature, collects
+//! them on a schedule while serving the last ones over HTTP.
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Instant;
+
+use super::response::{CalibrationStatus, DeviceStatus, ProbeSlope};
+use super::sensor::PhSensor;
+use super::transport::EzoTransport;
+use super::EzoError;
+
+/// Collections between queries of the slope, calibration and status of the
+/// devices, which seldom change, by default.
+pub const STATE_EVERY: u32 = 10;
+
+/// Upper bounds of the read latency buckets, in seconds. A reading takes
+/// at least 900 ms.
+pub const LATENCY_BUCKETS: [f64; 8] = [0.9, 1.0, 1.1, 1.25, 1.5, 2.0, 3.0, 5.0];
+
+/// A cumulative histogram, as Prometheus expects it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// An empty histogram, with buckets up to each of `bounds`.
+    pub fn new(bounds: &[f64]) -> Histogram {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Records a value.
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of the values recorded.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+}
+
+/// Metrics of a device, as of its last collection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceMetrics {
+    /// I2C bus of the device.
+    pub bus: u8,
+    pub address: u16,
+    /// Name of the device.
+    pub name: String,
+    /// Whether every query of the last collection succeeded.
+    pub up: bool,
+    pub reading: Option<f64>,
+    /// Temperature used for compensation, in °C.
+    pub temperature: Option<f64>,
+    pub slope: Option<ProbeSlope>,
+    pub calibration: Option<CalibrationStatus>,
+    pub status: Option<DeviceStatus>,
+    /// Readings attempted.
+    pub reads: u64,
+    /// Errors of any query, by kind.
+    pub errors: BTreeMap<String, u64>,
+    /// Time taken by the readings that succeeded, in seconds.
+    pub read_latency: Histogram,
+}
+
+impl DeviceMetrics {
+    /// Metrics of a device that has not been collected yet.
+    pub fn new(bus: u8, address: u16, name: &str) -> DeviceMetrics {
+        DeviceMetrics {
+            bus,
+            address,
+            name: name.to_string(),
+            up: false,
+            reading: None,
+            temperature: None,
+            slope: None,
+            calibration: None,
+            status: None,
+            reads: 0,
+            errors: BTreeMap::new(),
+            read_latency: Histogram::new(&LATENCY_BUCKETS),
+        }
+    }
+
+    /// Takes a reading, and queries the compensation temperature, slope,
+    /// calibration and status of the device. Values that cannot be queried
+    /// are left out, and their errors counted.
+    pub fn collect<T: EzoTransport>(&mut self, sensor: &mut PhSensor<T>) {
+        self.collect_reading(sensor);
+        self.collect_state(sensor);
+    }
+
+    /// Takes a reading, and queries the compensation temperature.
+    pub fn collect_reading<T: EzoTransport>(&mut self, sensor: &mut PhSensor<T>) {
+        self.up = true;
+        self.reads += 1;
+        let start = Instant::now();
+        let reading = sensor.read();
+        if reading.is_ok() {
+            let elapsed = start.elapsed();
+            let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+            self.read_latency.observe(seconds);
+        }
+        self.reading = self.check(reading).map(|r| r.0);
+        let temperature = sensor.temperature();
+        self.temperature = self.check(temperature).map(|t| t.0);
+    }
+
+    /// Queries the slope, calibration and status of the device.
+    pub fn collect_state<T: EzoTransport>(&mut self, sensor: &mut PhSensor<T>) {
+        let slope = sensor.slope();
+        self.slope = self.check(slope);
+        let calibration = sensor.calibration_status();
+        self.calibration = self.check(calibration);
+        let status = sensor.status();
+        self.status = self.check(status);
+    }
+
+    fn check<R>(&mut self, result: Result<R, EzoError>) -> Option<R> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.up = false;
+                *self.errors.entry(format!("{:?}", e.kind())).or_insert(0) += 1;
+                None
+            }
+        }
+    }
+
+    /// Labels of the device's metrics, without braces.
+    fn labels(&self) -> String {
+        format!(
+            "bus=\"{}\",address=\"0x{:02x}\",device=\"{}\"",
+            self.bus,
+            self.address,
+            escape(&self.name)
+        )
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn calibration_points(status: CalibrationStatus) -> u8 {
+    match status {
+        CalibrationStatus::NotCalibrated => 0,
+        CalibrationStatus::OnePoint => 1,
+        CalibrationStatus::TwoPoint => 2,
+        CalibrationStatus::ThreePoint => 3,
+    }
+}
+
+/// Writes a metric family: its help and type, then a sample for each
+/// device that has a value.
+fn family<F>(out: &mut String, name: &str, kind: &str, help: &str, devices: &[&DeviceMetrics], f: F)
+where
+    F: Fn(&DeviceMetrics) -> Vec<(String, f64)>,
+{
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    for device in devices {
+        let labels = device.labels();
+        for (extra, value) in f(device) {
+            let sep = if extra.is_empty() { "" } else { "," };
+            writeln!(out, "{}{{{}{}{}}} {}", name, labels, sep, extra, value).unwrap();
+        }
+    }
+}
+
+/// Writes a histogram family: its help and type, then the buckets, sum
+/// and count of each device.
+fn histogram<F>(out: &mut String, name: &str, help: &str, devices: &[&DeviceMetrics], f: F)
+where
+    F: Fn(&DeviceMetrics) -> &Histogram,
+{
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} histogram", name).unwrap();
+    for device in devices {
+        let labels = device.labels();
+        let hist = f(device);
+        for (bound, count) in hist.bounds.iter().zip(hist.counts.iter()) {
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, hist.count
+        )
+        .unwrap();
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, hist.sum).unwrap();
+        writeln!(out, "{}_count{{{}}} {}", name, labels, hist.count).unwrap();
+    }
+}
+
+fn gauge(value: Option<f64>) -> Vec<(String, f64)> {
+    value.into_iter().map(|v| (String::new(), v)).collect()
+}
+
+/// Writes the metrics of the devices, in the Prometheus text format.
+pub fn render(devices: &[&DeviceMetrics]) -> String {
+    let mut out = String::new();
+    family(
+        &mut out,
+        "ezo_ph_up",
+        "gauge",
+        "Whether the last collection succeeded.",
+        devices,
+        |d| gauge(Some(if d.up { 1.0 } else { 0.0 })),
+    );
+    family(
+        &mut out,
+        "ezo_ph_ph",
+        "gauge",
+        "Latest pH reading.",
+        devices,
+        |d| gauge(d.reading),
+    );
+    family(
+        &mut out,
+        "ezo_ph_temperature_celsius",
+        "gauge",
+        "Temperature used for compensation.",
+        devices,
+        |d| gauge(d.temperature),
+    );
+    family(
+        &mut out,
+        "ezo_ph_slope_acid_percent",
+        "gauge",
+        "Probe slope on the acid side, in percent of the ideal.",
+        devices,
+        |d| gauge(d.slope.map(|s| s.acid_end)),
+    );
+    family(
+        &mut out,
+        "ezo_ph_slope_base_percent",
+        "gauge",
+        "Probe slope on the base side, in percent of the ideal.",
+        devices,
+        |d| gauge(d.slope.map(|s| s.base_end)),
+    );
+    family(
+        &mut out,
+        "ezo_ph_zero_offset_millivolts",
+        "gauge",
+        "Probe potential at pH 7, when the firmware reports it.",
+        devices,
+        |d| gauge(d.slope.and_then(|s| s.zero_offset_mv)),
+    );
+    family(
+        &mut out,
+        "ezo_ph_calibration_points",
+        "gauge",
+        "Number of calibrated points.",
+        devices,
+        |d| gauge(d.calibration.map(|c| f64::from(calibration_points(c)))),
+    );
+    family(
+        &mut out,
+        "ezo_ph_vcc_volts",
+        "gauge",
+        "Supply voltage of the chip.",
+        devices,
+        |d| gauge(d.status.as_ref().map(|s| s.vcc_voltage)),
+    );
+    family(
+        &mut out,
+        "ezo_ph_restart_reason",
+        "gauge",
+        "Reason of the last restart of the chip, as a label.",
+        devices,
+        |d| {
+            d.status
+                .as_ref()
+                .map(|s| (format!("reason=\"{}\"", s.restart_reason), 1.0))
+                .into_iter()
+                .collect()
+        },
+    );
+    family(
+        &mut out,
+        "ezo_ph_reads_total",
+        "counter",
+        "Readings attempted.",
+        devices,
+        |d| vec![(String::new(), d.reads as f64)],
+    );
+    family(
+        &mut out,
+        "ezo_ph_errors_total",
+        "counter",
+        "Failed queries, by kind of error.",
+        devices,
+        |d| {
+            d.errors
+                .iter()
+                .map(|(kind, count)| (format!("kind=\"{}\"", kind), *count as f64))
+                .collect()
+        },
+    );
+    histogram(
+        &mut out,
+        "ezo_ph_read_duration_seconds",
+        "Time taken by the readings.",
+        devices,
+        |d| &d.read_latency,
+    );
+    out
+}
+
+/// Devices whose metrics are exported. Every collection takes a reading
+/// of each device, but their slope, calibration and status are only
+/// queried every few collections.
+#[derive(Debug)]
+pub struct Exporter<T: EzoTransport> {
+    devices: Vec<(PhSensor<T>, DeviceMetrics)>,
+    state_every: u32,
+    /// Collections left before the state is queried again.
+    until_state: u32,
+}
+
+impl<T: EzoTransport> Exporter<T> {
+    /// An exporter without devices.
+    pub fn new() -> Exporter<T> {
+        Exporter {
+            devices: Vec::new(),
+            state_every: STATE_EVERY,
+            until_state: 0,
+        }
+    }
+
+    /// Queries the slope, calibration and status of the devices every
+    /// `every` collections, starting with the first one. 0 is taken as 1.
+    pub fn set_state_every(&mut self, every: u32) {
+        self.state_every = cmp::max(every, 1);
+    }
+
+    /// Adds a device, on I2C bus `bus`, named `name`.
+    pub fn add(&mut self, sensor: PhSensor<T>, bus: u8, name: &str) {
+        let metrics = DeviceMetrics::new(bus, sensor.address(), name);
+        self.devices.push((sensor, metrics));
+    }
+
+    /// Metrics of the devices, as of their last collection.
+    pub fn metrics(&self) -> Vec<&DeviceMetrics> {
+        self.devices.iter().map(|(_, metrics)| metrics).collect()
+    }
+
+    /// Collects the metrics of every device.
+    pub fn collect(&mut self) {
+        let state = self.until_state == 0;
+        if state {
+            self.until_state = self.state_every;
+        }
+        self.until_state -= 1;
+        for (sensor, metrics) in &mut self.devices {
+            metrics.collect_reading(sensor);
+            if state {
+                metrics.collect_state(sensor);
+            }
+        }
+    }
+
+    /// Collects the metrics of every device, and writes them in the
+    /// Prometheus text format.
+    pub fn scrape(&mut self) -> String {
+        self.collect();
+        render(&self.metrics())
+    }
+}
+
+impl<T: EzoTransport> Default for Exporter<T> {
+    fn default() -> Exporter<T> {
+        Exporter::new()
+    }
+}
+
+#[cfg(feature = "exporter")]
+mod http {
+    use std::io;
+    use std::net::ToSocketAddrs;
+    use std::time::{Duration, Instant};
+
+    use super::{render, Exporter};
+    use transport::EzoTransport;
+
+    use tiny_http::{Header, Method, Response, Server};
+
+    impl<T: EzoTransport> Exporter<T> {
+        /// Serves the metrics at `/metrics`, over HTTP on `addr`, until
+        /// the server fails. The devices are collected every `interval`,
+        /// between requests, which get the metrics of the last collection.
+        pub fn serve<A: ToSocketAddrs>(&mut self, addr: A, interval: Duration) -> io::Result<()> {
+            // `io::Error::other` needs a newer compiler.
+            #[allow(clippy::io_other_error)]
+            let server = Server::http(addr).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let content_type: Header = "Content-Type: text/plain; version=0.0.4".parse().unwrap();
+            let mut next = Instant::now();
+            loop {
+                let now = Instant::now();
+                if now >= next {
+                    self.collect();
+                    next = Instant::now() + interval;
+                    continue;
+                }
+                let request = match server.recv_timeout(next - now)? {
+                    Some(request) => request,
+                    None => continue,
+                };
+                let path = request.url().split('?').next().unwrap_or("").to_string();
+                let result = match (request.method(), path.as_ref()) {
+                    (&Method::Get, "/metrics") => {
+                        let body = render(&self.metrics());
+                        let response =
+                            Response::from_string(body).with_header(content_type.clone());
+                        request.respond(response)
+                    }
+                    _ => request.respond(Response::from_string("not found").with_status_code(404)),
+                };
+                // A client that went away does not stop the server.
+                let _ = result;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockDevice;
+    use simulator::Simulator;
+
+    #[test]
+    fn histogram_is_cumulative() {
+        let mut hist = Histogram::new(&[1.0, 2.0]);
+        hist.observe(0.5);
+        hist.observe(1.5);
+        hist.observe(3.0);
+        assert_eq!(hist.counts, vec![1, 2]);
+        assert_eq!(hist.count(), 3);
+        assert_eq!(hist.sum(), 5.0);
+    }
+
+    #[test]
+    fn exports_simulated_device() {
+        let mut exporter = Exporter::new();
+        let mut sensor = PhSensor::new(Simulator::default(), 0x63);
+        sensor.calibrate_mid(7.0).unwrap();
+        exporter.add(sensor, 1, "tank \"1\"");
+        let text = exporter.scrape();
+
+        let labels = "bus=\"1\",address=\"0x63\",device=\"tank \\\"1\\\"\"";
+        for line in &[
+            "# TYPE ezo_ph_ph gauge".to_string(),
+            format!("ezo_ph_up{{{}}} 1", labels),
+            format!("ezo_ph_ph{{{}}} 7", labels),
+            format!("ezo_ph_temperature_celsius{{{}}} 25", labels),
+            format!("ezo_ph_calibration_points{{{}}} 1", labels),
+            format!(
+                "ezo_ph_restart_reason{{{},reason=\"powered-off\"}} 1",
+                labels
+            ),
+            format!("ezo_ph_reads_total{{{}}} 1", labels),
+            format!(
+                "ezo_ph_read_duration_seconds_bucket{{{},le=\"+Inf\"}} 1",
+                labels
+            ),
+            format!("ezo_ph_read_duration_seconds_count{{{}}} 1", labels),
+            "# TYPE ezo_ph_read_duration_seconds histogram".to_string(),
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                text
+            );
+        }
+        assert!(text.contains("ezo_ph_vcc_volts{"));
+        assert!(!text.contains("ezo_ph_errors_total{"));
+    }
+
+    #[test]
+    fn queries_state_less_often_than_readings() {
+        let mut exporter = Exporter::new();
+        exporter.set_state_every(2);
+        exporter.add(PhSensor::new(MockDevice::new(), 0x63), 1, "tank-1");
+        for _ in 0..3 {
+            exporter.collect();
+        }
+        assert_eq!(exporter.metrics()[0].reads, 3);
+
+        let (sensor, _) = exporter.devices.pop().unwrap();
+        let commands = sensor.into_inner().commands().join(" ");
+        assert_eq!(
+            commands,
+            "R T,? SLOPE,? CAL,? STATUS R T,? R T,? SLOPE,? CAL,? STATUS"
+        );
+    }
+
+    #[test]
+    fn counts_errors_and_leaves_out_values() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(2)
+            .queue_data("?T,19.500")
+            .queue_data("?SLOPE,99.7,100.3")
+            .queue_data("?CAL,2")
+            .queue_code(2);
+        let mut exporter = Exporter::new();
+        exporter.add(PhSensor::new(dev, 0x64), 3, "tank-2");
+        let text = exporter.scrape();
+
+        let metrics = exporter.metrics();
+        assert!(!metrics[0].up);
+        assert_eq!(metrics[0].reading, None);
+        assert_eq!(metrics[0].temperature, Some(19.5));
+        assert_eq!(metrics[0].errors.get("DeviceErrorResponse"), Some(&2));
+        assert_eq!(metrics[0].read_latency.count(), 0);
+
+        let labels = "bus=\"3\",address=\"0x64\",device=\"tank-2\"";
+        assert!(text.contains(&format!("ezo_ph_up{{{}}} 0\n", labels)));
+        assert!(!text.contains("ezo_ph_ph{"));
+        assert!(text.contains(&format!("ezo_ph_slope_base_percent{{{}}} 100.3\n", labels)));
+        assert!(text.contains(&format!(
+            "ezo_ph_errors_total{{{},kind=\"DeviceErrorResponse\"}} 2\n",
+            labels
+        )));
+    }
+}
@@ -0,0 +1,178 @@
+//! Scripted mock of the PH EZO Chip, for testing code without hardware.
+//!
+//! The mock records every command written to it, and every wait, and
+//! replies with responses that were queued beforehand.
+use std::collections::VecDeque;
+
+use super::transport::EzoTransport;
+use super::{ErrorKind, EzoError};
+
+/// Response code for a successful request.
+pub const SUCCESS: u8 = 1;
+
+/// Response code for a syntax error.
+pub const SYNTAX_ERROR: u8 = 2;
+
+/// Response code for a request that is still being processed.
+pub const PENDING: u8 = 254;
+
+/// Response code for when there is no data to send.
+pub const NO_DATA: u8 = 255;
+
+/// Interactions with the mock device, in the order they happened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockEvent {
+    /// A command string was written.
+    Write(String),
+    /// The caller waited this many milliseconds.
+    Wait(u64),
+    /// A response was read.
+    Read,
+}
+
+/// A mock device that replies with queued responses.
+///
+/// Reading when no response is queued yields the `NO_DATA` code, just as
+/// the chip does when it has nothing to send.
+#[derive(Clone, Debug, Default)]
+pub struct MockDevice {
+    events: Vec<MockEvent>,
+    responses: VecDeque<(u8, String)>,
+}
+
+impl MockDevice {
+    /// Creates a mock device with no queued responses.
+    pub fn new() -> MockDevice {
+        MockDevice::default()
+    }
+
+    /// Queues a response with the given code and ASCII data.
+    pub fn queue_response(&mut self, code: u8, data: &str) -> &mut MockDevice {
+        self.responses.push_back((code, data.to_string()));
+        self
+    }
+
+    /// Queues a successful response with ASCII data.
+    pub fn queue_data(&mut self, data: &str) -> &mut MockDevice {
+        self.queue_response(SUCCESS, data)
+    }
+
+    /// Queues a successful response with no data.
+    pub fn queue_ack(&mut self) -> &mut MockDevice {
+        self.queue_response(SUCCESS, "")
+    }
+
+    /// Queues a response with the given code and no data.
+    pub fn queue_code(&mut self, code: u8) -> &mut MockDevice {
+        self.queue_response(code, "")
+    }
+
+    /// Number of queued responses that have not been read.
+    pub fn pending_responses(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// All the interactions with the device, in order.
+    pub fn events(&self) -> &[MockEvent] {
+        &self.events
+    }
+
+    /// The command strings that were written, in order.
+    pub fn commands(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter_map(|e| match *e {
+                MockEvent::Write(ref cmd) => Some(cmd.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The waits, in milliseconds, in order.
+    pub fn waits(&self) -> Vec<u64> {
+        self.events
+            .iter()
+            .filter_map(|e| match *e {
+                MockEvent::Wait(millis) => Some(millis),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forgets the recorded interactions, keeping queued responses.
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl EzoTransport for MockDevice {
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError> {
+        self.events.push(MockEvent::Write(cmd.to_string()));
+        Ok(())
+    }
+
+    fn wait(&mut self, millis: u64) {
+        self.events.push(MockEvent::Wait(millis));
+    }
+
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        self.events.push(MockEvent::Read);
+        let (code, data) = self
+            .responses
+            .pop_front()
+            .unwrap_or_else(|| (NO_DATA, String::new()));
+        let bytes = data.as_bytes();
+        if bytes.len() + 2 > buf.len() {
+            return Err(ErrorKind::MalformedResponse)?;
+        }
+        buf[0] = code;
+        buf[1..=bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len() + 1] = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{CalibrationMid, Reading, TemperatureCompensation, TransportCommand};
+    use response::SensorReading;
+
+    #[test]
+    fn records_command_sequence_and_timing() {
+        let mut dev = MockDevice::new();
+        dev.queue_ack().queue_ack().queue_data("7.012");
+
+        CalibrationMid(7.0).run_on(&mut dev).unwrap();
+        TemperatureCompensation(25.0).run_on(&mut dev).unwrap();
+        let reading = Reading.run_on(&mut dev).unwrap();
+
+        assert_eq!(reading, SensorReading(7.012));
+        assert_eq!(dev.commands(), vec!["CAL,MID,7.00", "T,25.000", "R"]);
+        assert_eq!(dev.waits(), vec![900, 300, 900]);
+        assert_eq!(
+            &dev.events()[..3],
+            &[
+                MockEvent::Write("CAL,MID,7.00".to_string()),
+                MockEvent::Wait(900),
+                MockEvent::Read,
+            ]
+        );
+        assert_eq!(dev.pending_responses(), 0);
+    }
+
+    #[test]
+    fn queued_error_codes_yield_err() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(SYNTAX_ERROR).queue_code(PENDING);
+
+        let err = Reading.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+
+        let err = Reading.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+
+        let err = Reading.run_on(&mut dev).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoDataExpectedResponse);
+    }
+}
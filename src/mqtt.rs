@@ -0,0 +1,529 @@
+//! Publishing of readings and device state over MQTT.
+//!
+//! A `Publisher` sends the readings of a device, with their time and the
+//! name and address of the device, to its `reading` topic. The calibration
+//! status, probe slope and device information are retained on topics of
+//! their own, so that new subscribers get them at once. EZO command strings
+//! received on the `command` topic, such as `Cal,mid,7.00` or `T,21.5`, are
+//! run on the device, and their results sent to the `response` topic. Only
+//! calibrations, temperature compensation and queries that change nothing
+//! on the device are run; any other command is refused.
+//!
+//! The broker is reached through an `MqttClient`. With the `mqtt` feature,
+//! it is implemented for `rumqttc::Client`, and `Publisher::run` publishes
+//! the readings on a schedule while handling the commands.
+use std::fmt;
+
+use super::command::PhCommand;
+use super::sensor::PhSensor;
+use super::transport::EzoTransport;
+use super::EzoError;
+
+use chrono::{DateTime, Utc};
+
+use failure::Fail;
+
+use serde_json;
+
+/// Topics of a device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Topics {
+    /// Readings, as `ReadingMessage`s.
+    pub reading: String,
+    /// Calibration status, retained.
+    pub calibration: String,
+    /// Probe slope, retained.
+    pub slope: String,
+    /// Device type and firmware version, retained.
+    pub info: String,
+    /// EZO command strings to run on the device.
+    pub command: String,
+    /// Results of the commands, as `CommandResult`s.
+    pub response: String,
+}
+
+impl Topics {
+    /// Topics under `<prefix>/<device>/`.
+    pub fn new(prefix: &str, device: &str) -> Topics {
+        let topic = |name: &str| format!("{}/{}/{}", prefix, device, name);
+        Topics {
+            reading: topic("reading"),
+            calibration: topic("calibration"),
+            slope: topic("slope"),
+            info: topic("info"),
+            command: topic("command"),
+            response: topic("response"),
+        }
+    }
+}
+
+/// A reading, as published.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReadingMessage {
+    /// When the reading was taken.
+    pub timestamp: DateTime<Utc>,
+    /// Name of the device.
+    pub device: String,
+    pub address: u16,
+    pub ph: f64,
+    /// Temperature used for compensation, in °C, if known.
+    pub temperature: Option<f64>,
+}
+
+/// Device information, as published.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InfoMessage {
+    /// Device type, as reported by the `I` command.
+    pub device: String,
+    /// Firmware version, as reported by the `I` command.
+    pub firmware: String,
+}
+
+/// Result of a command received on the command topic, as published.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandResult {
+    /// When the command was run.
+    pub timestamp: DateTime<Utc>,
+    /// The command string, as received.
+    pub command: String,
+    /// The response of the device, if the command succeeded.
+    pub response: Option<String>,
+    /// Why the command failed, if it did.
+    pub error: Option<String>,
+    /// Why the calibration made by the command could not be recorded in
+    /// the history, if it could not.
+    pub warning: Option<String>,
+}
+
+/// A connection to an MQTT broker.
+pub trait MqttClient {
+    /// Publishes `payload` on `topic`. If `retain` is set, the broker
+    /// keeps it for new subscribers.
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), MqttError>;
+
+    /// Subscribes to `topic`.
+    fn subscribe(&mut self, topic: &str) -> Result<(), MqttError>;
+}
+
+/// Publishes the readings and state of a device, and runs the commands it
+/// is sent.
+#[derive(Debug)]
+pub struct Publisher<T: EzoTransport, C: MqttClient> {
+    sensor: PhSensor<T>,
+    client: C,
+    name: String,
+    topics: Topics,
+}
+
+impl<T: EzoTransport, C: MqttClient> Publisher<T, C> {
+    /// Publishes the device `name`, on `topics`, through `client`.
+    pub fn new(sensor: PhSensor<T>, client: C, name: &str, topics: Topics) -> Publisher<T, C> {
+        Publisher {
+            sensor,
+            client,
+            name: name.to_string(),
+            topics,
+        }
+    }
+
+    /// The device.
+    pub fn sensor(&self) -> &PhSensor<T> {
+        &self.sensor
+    }
+
+    /// The client used to reach the broker.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Topics of the device.
+    pub fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    /// Subscribes to the command topic, and publishes the state of the
+    /// device. To be called whenever the client connects to the broker.
+    pub fn connect(&mut self) -> Result<(), MqttError> {
+        self.client.subscribe(&self.topics.command)?;
+        self.publish_state()
+    }
+
+    /// Takes a reading, and publishes it.
+    pub fn publish_reading(&mut self) -> Result<ReadingMessage, MqttError> {
+        let reading = self.sensor.read()?;
+        let message = ReadingMessage {
+            timestamp: Utc::now(),
+            device: self.name.clone(),
+            address: self.sensor.address(),
+            ph: reading.0,
+            temperature: self.sensor.last_temperature(),
+        };
+        let payload = serde_json::to_vec(&message)?;
+        self.client.publish(&self.topics.reading, &payload, false)?;
+        Ok(message)
+    }
+
+    /// Queries the calibration status and probe slope, and publishes them.
+    pub fn publish_calibration(&mut self) -> Result<(), MqttError> {
+        let calibration = serde_json::to_vec(&self.sensor.calibration_status()?)?;
+        self.client
+            .publish(&self.topics.calibration, &calibration, true)?;
+        let slope = serde_json::to_vec(&self.sensor.slope()?)?;
+        self.client.publish(&self.topics.slope, &slope, true)
+    }
+
+    /// Queries the calibration status, probe slope and device information,
+    /// and publishes them.
+    pub fn publish_state(&mut self) -> Result<(), MqttError> {
+        self.publish_calibration()?;
+        let info = self.sensor.info()?;
+        let message = InfoMessage {
+            device: info.device,
+            firmware: info.firmware,
+        };
+        let payload = serde_json::to_vec(&message)?;
+        self.client.publish(&self.topics.info, &payload, true)
+    }
+
+    /// Handles a message from the broker. Commands that may be sent
+    /// remotely are run on the device, and their results published,
+    /// whether they succeed or not; after a calibration, the calibration
+    /// state is published again, and a failure to record it in the
+    /// sensor's history is published as a warning. Other commands are
+    /// refused with an error result. Messages on other topics are ignored.
+    pub fn handle(&mut self, topic: &str, payload: &[u8]) -> Result<(), MqttError> {
+        if topic != self.topics.command {
+            return Ok(());
+        }
+        let command = String::from_utf8_lossy(payload).trim().to_string();
+        let mut calibrated = false;
+        let result = match command.parse::<PhCommand>() {
+            Ok(ref cmd) if !is_remote(cmd) => Err(format!("command not allowed: {}", command)),
+            Ok(cmd) => {
+                let result = self.sensor.execute(&cmd);
+                calibrated = calibrates(&cmd) && result.is_ok();
+                result.map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        let message = CommandResult {
+            timestamp: Utc::now(),
+            command,
+            response: result.as_ref().ok().map(|r| r.to_string()),
+            error: result.err(),
+            warning: self.sensor.take_history_error().map(|e| e.to_string()),
+        };
+        let payload = serde_json::to_vec(&message)?;
+        self.client
+            .publish(&self.topics.response, &payload, false)?;
+        if calibrated {
+            self.publish_calibration()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the command may be sent remotely: it calibrates, sets the
+/// temperature compensation, or only queries the device.
+fn is_remote(cmd: &PhCommand) -> bool {
+    matches!(
+        *cmd,
+        PhCommand::CalibrationMid(_)
+            | PhCommand::CalibrationLow(_)
+            | PhCommand::CalibrationHigh(_)
+            | PhCommand::CalibrationClear(_)
+            | PhCommand::CalibrationState(_)
+            | PhCommand::TemperatureCompensation(_)
+            | PhCommand::CompensatedTemperatureValue(_)
+            | PhCommand::Reading(_)
+            | PhCommand::ReadingWithTemperature(_)
+            | PhCommand::ExtendedScaleState(_)
+            | PhCommand::Slope(_)
+            | PhCommand::NameState(_)
+            | PhCommand::DeviceInformation(_)
+            | PhCommand::ExportInfo(_)
+            | PhCommand::LedState(_)
+            | PhCommand::ProtocolLockState(_)
+            | PhCommand::Status(_)
+    )
+}
+
+/// Whether the command changes the calibration.
+fn calibrates(cmd: &PhCommand) -> bool {
+    matches!(
+        *cmd,
+        PhCommand::CalibrationMid(_)
+            | PhCommand::CalibrationLow(_)
+            | PhCommand::CalibrationHigh(_)
+            | PhCommand::CalibrationClear(_)
+    )
+}
+
+/// Errors of the publisher.
+#[derive(Debug)]
+pub enum MqttError {
+    /// The client could not publish, subscribe, or reach the broker.
+    Client(String),
+    /// The device could not be queried.
+    Device(EzoError),
+    /// A message could not be serialized.
+    Json(serde_json::Error),
+}
+
+impl From<EzoError> for MqttError {
+    fn from(error: EzoError) -> MqttError {
+        MqttError::Device(error)
+    }
+}
+
+impl From<serde_json::Error> for MqttError {
+    fn from(error: serde_json::Error) -> MqttError {
+        MqttError::Json(error)
+    }
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MqttError::Client(ref e) => write!(f, "MQTT client error: {}", e),
+            MqttError::Device(ref e) => write!(f, "device error: {}", e),
+            MqttError::Json(ref e) => write!(f, "could not serialize message: {}", e),
+        }
+    }
+}
+
+impl Fail for MqttError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            MqttError::Client(_) => None,
+            MqttError::Device(ref e) => Some(e),
+            MqttError::Json(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod client {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{MqttClient, MqttError, Publisher};
+    use transport::EzoTransport;
+
+    use rumqttc::{Client, Connection, Event, Packet, QoS, RecvTimeoutError};
+
+    /// Time to wait before reconnecting to the broker.
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+    impl MqttClient for Client {
+        fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), MqttError> {
+            self.try_publish(topic, QoS::AtLeastOnce, retain, payload.to_vec())
+                .map_err(|e| MqttError::Client(e.to_string()))
+        }
+
+        fn subscribe(&mut self, topic: &str) -> Result<(), MqttError> {
+            self.try_subscribe(topic, QoS::AtLeastOnce)
+                .map_err(|e| MqttError::Client(e.to_string()))
+        }
+    }
+
+    impl<T: EzoTransport> Publisher<T, Client> {
+        /// Publishes a reading every `interval`, and handles the commands,
+        /// while driving the `connection` of the client. The state is
+        /// published on every connection to the broker. Errors are passed
+        /// to `on_error`, and do not stop the publisher; lost connections
+        /// are retried. Returns once the client is gone.
+        pub fn run<F>(&mut self, connection: &mut Connection, interval: Duration, mut on_error: F)
+        where
+            F: FnMut(MqttError),
+        {
+            let mut next = Instant::now();
+            loop {
+                let now = Instant::now();
+                if now >= next {
+                    if let Err(e) = self.publish_reading() {
+                        on_error(e);
+                    }
+                    next += interval;
+                    continue;
+                }
+                let result = match connection.recv_timeout(next - now) {
+                    Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => self.connect(),
+                    Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                        self.handle(&publish.topic, &publish.payload)
+                    }
+                    Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => Ok(()),
+                    Ok(Err(e)) => {
+                        thread::sleep(RECONNECT_DELAY);
+                        Err(MqttError::Client(e.to_string()))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+                if let Err(e) = result {
+                    on_error(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockDevice;
+    use response::{CalibrationStatus, ProbeSlope};
+    use simulator::Simulator;
+
+    /// Stands in for the broker, keeping what is sent to it.
+    #[derive(Debug, Default)]
+    struct FakeClient {
+        published: Vec<(String, Vec<u8>, bool)>,
+        subscriptions: Vec<String>,
+    }
+
+    impl FakeClient {
+        /// Messages published on `topic`, with whether they are retained.
+        fn messages(&self, topic: &str) -> Vec<(String, bool)> {
+            self.published
+                .iter()
+                .filter(|(t, _, _)| t == topic)
+                .map(|(_, payload, retain)| (String::from_utf8(payload.clone()).unwrap(), *retain))
+                .collect()
+        }
+    }
+
+    impl MqttClient for FakeClient {
+        fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), MqttError> {
+            self.published
+                .push((topic.to_string(), payload.to_vec(), retain));
+            Ok(())
+        }
+
+        fn subscribe(&mut self, topic: &str) -> Result<(), MqttError> {
+            self.subscriptions.push(topic.to_string());
+            Ok(())
+        }
+    }
+
+    fn publisher() -> Publisher<Simulator, FakeClient> {
+        let sensor = PhSensor::new(Simulator::default(), 0x63);
+        let topics = Topics::new("plant", "tank-1");
+        Publisher::new(sensor, FakeClient::default(), "tank-1", topics)
+    }
+
+    #[test]
+    fn publishes_readings_with_the_device() {
+        let mut publisher = publisher();
+        let sent = publisher.publish_reading().unwrap();
+        assert_eq!(sent.device, "tank-1");
+        assert_eq!(sent.address, 0x63);
+        assert_eq!(sent.temperature, None);
+
+        let messages = publisher.client().messages("plant/tank-1/reading");
+        assert_eq!(messages.len(), 1);
+        assert!(!messages[0].1);
+        let received: ReadingMessage = serde_json::from_str(&messages[0].0).unwrap();
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn retains_state_on_connection() {
+        let mut publisher = publisher();
+        publisher.connect().unwrap();
+        let client = publisher.client();
+        assert_eq!(client.subscriptions, vec!["plant/tank-1/command"]);
+
+        let calibration = client.messages("plant/tank-1/calibration");
+        assert!(calibration[0].1);
+        let status: CalibrationStatus = serde_json::from_str(&calibration[0].0).unwrap();
+        assert_eq!(status, CalibrationStatus::NotCalibrated);
+
+        let slope = client.messages("plant/tank-1/slope");
+        assert!(slope[0].1);
+        assert!(serde_json::from_str::<ProbeSlope>(&slope[0].0).is_ok());
+
+        let info = client.messages("plant/tank-1/info");
+        assert!(info[0].1);
+        let info: InfoMessage = serde_json::from_str(&info[0].0).unwrap();
+        assert_eq!(info.device, "pH");
+    }
+
+    #[test]
+    fn runs_remote_commands() {
+        let mut publisher = publisher();
+        publisher
+            .handle("plant/tank-1/command", b"Cal,mid,7.00")
+            .unwrap();
+        publisher
+            .handle("plant/tank-1/command", b"T,21.5\n")
+            .unwrap();
+        publisher.handle("plant/tank-1/command", b"Bogus").unwrap();
+        publisher.handle("plant/tank-2/command", b"Sleep").unwrap();
+        assert_eq!(publisher.sensor().last_temperature(), Some(21.5));
+
+        let client = publisher.client();
+        let results: Vec<CommandResult> = client
+            .messages("plant/tank-1/response")
+            .iter()
+            .map(|(payload, _)| serde_json::from_str(payload).unwrap())
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].command, "Cal,mid,7.00");
+        assert_eq!(results[0].response, Some("ok".to_string()));
+        assert_eq!(results[1].command, "T,21.5");
+        assert_eq!(results[1].error, None);
+        assert_eq!(results[2].response, None);
+        assert!(results[2].error.is_some());
+
+        let calibration = client.messages("plant/tank-1/calibration");
+        assert_eq!(calibration.len(), 1);
+        let status: CalibrationStatus = serde_json::from_str(&calibration[0].0).unwrap();
+        assert_eq!(status, CalibrationStatus::OnePoint);
+        assert!(!publisher.sensor().transport().is_asleep());
+    }
+
+    #[test]
+    fn refuses_commands_that_change_the_device() {
+        let sensor = PhSensor::new(MockDevice::new(), 0x63);
+        let topics = Topics::new("plant", "tank-1");
+        let mut publisher = Publisher::new(sensor, FakeClient::default(), "tank-1", topics);
+        publisher
+            .handle("plant/tank-1/command", b"Factory")
+            .unwrap();
+        publisher
+            .handle("plant/tank-1/command", b"I2C,100")
+            .unwrap();
+        assert!(publisher.sensor().transport().commands().is_empty());
+
+        let results: Vec<CommandResult> = publisher
+            .client()
+            .messages("plant/tank-1/response")
+            .iter()
+            .map(|(payload, _)| serde_json::from_str(payload).unwrap())
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].response, None);
+        assert_eq!(
+            results[0].error,
+            Some("command not allowed: Factory".to_string())
+        );
+        assert_eq!(
+            results[1].error,
+            Some("command not allowed: I2C,100".to_string())
+        );
+    }
+
+    #[test]
+    fn device_errors_are_returned() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(2);
+        let sensor = PhSensor::new(dev, 0x63);
+        let topics = Topics::new("plant", "tank-1");
+        let mut publisher = Publisher::new(sensor, FakeClient::default(), "tank-1", topics);
+        match publisher.publish_reading() {
+            Err(MqttError::Device(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(publisher.client().published.is_empty());
+    }
+}
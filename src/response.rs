@@ -5,7 +5,7 @@ use std::fmt;
 use std::str::FromStr;
 
 use super::{ErrorKind, EzoError};
-use failure::ResultExt;
+use failure::{err_msg, ResultExt};
 
 pub use ezo_common::response::{
     DeviceInfo, DeviceStatus, Exported, ExportedInfo, LedStatus, ProtocolLockStatus,
@@ -18,8 +18,23 @@ pub const PROBE_LOWER_LIMIT: f64 = 0.0;
 /// Maximum possible pH reading, per pH probe data sheet.
 pub const PROBE_UPPER_LIMIT: f64 = 14.0;
 
+/// Minimum possible pH reading on the extended pH scale.
+pub const EXTENDED_LOWER_LIMIT: f64 = -1.6;
+
+/// Maximum possible pH reading on the extended pH scale.
+pub const EXTENDED_UPPER_LIMIT: f64 = 15.6;
+
+/// Maximum length of the name stored on the device.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+/// Whether `name` can be stored on the device: up to 16 printable ASCII
+/// characters, excluding spaces and commas.
+pub fn is_valid_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LENGTH && name.bytes().all(|b| b.is_ascii_graphic() && b != b',')
+}
+
 /// Calibration status of the PH EZO chip.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CalibrationStatus {
     OnePoint,
     TwoPoint,
@@ -75,19 +90,77 @@ impl fmt::Display for CalibrationStatus {
     }
 }
 
-/// A temperature reading
+/// pH scale used by the PH EZO chip for its readings.
+#[derive(Copy, Clone, PartialEq)]
+pub enum PhScale {
+    Standard,
+    Extended,
+}
+
+impl PhScale {
+    /// Parses the result of the "pHext,?" command to query the device's
+    /// pH scale.
+    pub fn parse(response: &str) -> Result<PhScale, EzoError> {
+        match response.to_uppercase().as_ref() {
+            "?PHEXT,1" => Ok(PhScale::Extended),
+            "?PHEXT,0" => Ok(PhScale::Standard),
+            _ => Err(ErrorKind::ResponseParse.into()),
+        }
+    }
+
+    /// Minimum possible pH reading on this scale.
+    pub fn lower_limit(&self) -> f64 {
+        match *self {
+            PhScale::Standard => PROBE_LOWER_LIMIT,
+            PhScale::Extended => EXTENDED_LOWER_LIMIT,
+        }
+    }
+
+    /// Maximum possible pH reading on this scale.
+    pub fn upper_limit(&self) -> f64 {
+        match *self {
+            PhScale::Standard => PROBE_UPPER_LIMIT,
+            PhScale::Extended => EXTENDED_UPPER_LIMIT,
+        }
+    }
+}
+
+impl fmt::Debug for PhScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PhScale::Standard => write!(f, "?pHext,0"),
+            PhScale::Extended => write!(f, "?pHext,1"),
+        }
+    }
+}
+
+impl fmt::Display for PhScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PhScale::Standard => write!(f, "standard"),
+            PhScale::Extended => write!(f, "extended"),
+        }
+    }
+}
+
+/// A pH reading
 #[derive(Copy, Clone, PartialEq)]
 pub struct SensorReading(pub f64);
 
 impl SensorReading {
-    /// Parses the result of the "R" command to get a temperature reading.
-    /// Note that the returned value has no known units. It is your
-    /// responsibility to know the current `TemperatureScale` setting.
+    /// Parses the result of the "R" command to get a pH reading, on the
+    /// standard pH scale.
     pub fn parse(response: &str) -> Result<SensorReading, EzoError> {
+        SensorReading::parse_with_scale(response, PhScale::Standard)
+    }
+
+    /// Parses the result of the "R" command to get a pH reading, when the
+    /// device is using the given pH scale.
+    pub fn parse_with_scale(response: &str, scale: PhScale) -> Result<SensorReading, EzoError> {
         let val = f64::from_str(response).context(ErrorKind::ResponseParse)?;
 
         match val {
-            v if (v >= PROBE_LOWER_LIMIT) && (v <= PROBE_UPPER_LIMIT) => Ok(SensorReading(v)),
+            v if (v >= scale.lower_limit()) && (v <= scale.upper_limit()) => Ok(SensorReading(v)),
 
             _ => Err(ErrorKind::InvalidReading.into()),
         }
@@ -107,10 +180,12 @@ impl fmt::Display for SensorReading {
 }
 
 /// Slope-points for the current sensor probe
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProbeSlope {
     pub acid_end: f64,
     pub base_end: f64,
+    /// Zero-point offset, in millivolts. Only reported by newer firmware.
+    pub zero_offset_mv: Option<f64>,
 }
 
 impl ProbeSlope {
@@ -133,11 +208,21 @@ impl ProbeSlope {
                 return Err(ErrorKind::ResponseParse.into());
             };
 
+            let zero_offset_mv = if let Some(offset_str) = split.next() {
+                Some(f64::from_str(offset_str).context(ErrorKind::ResponseParse)?)
+            } else {
+                None
+            };
+
             if let Some(_) = split.next() {
                 return Err(ErrorKind::ResponseParse.into());
             }
 
-            Ok(ProbeSlope { acid_end, base_end })
+            Ok(ProbeSlope {
+                acid_end,
+                base_end,
+                zero_offset_mv,
+            })
         } else {
             Err(ErrorKind::ResponseParse.into())
         }
@@ -146,13 +231,21 @@ impl ProbeSlope {
 
 impl fmt::Debug for ProbeSlope {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "?SLOPE,{:.*},{:.*}", 3, self.acid_end, 3, self.base_end)
+        write!(f, "?SLOPE,{:.*},{:.*}", 3, self.acid_end, 3, self.base_end)?;
+        if let Some(offset) = self.zero_offset_mv {
+            write!(f, ",{:.*}", 3, offset)?;
+        }
+        Ok(())
     }
 }
 
 impl fmt::Display for ProbeSlope {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.*},{:.*}", 3, self.acid_end, 3, self.base_end)
+        write!(f, "{:.*},{:.*}", 3, self.acid_end, 3, self.base_end)?;
+        if let Some(offset) = self.zero_offset_mv {
+            write!(f, ",{:.*}", 3, offset)?;
+        }
+        Ok(())
     }
 }
 
@@ -186,9 +279,211 @@ impl fmt::Display for CompensationValue {
     }
 }
 
+/// Response codes and events sent by the PH EZO chip in UART mode.
+#[derive(Copy, Clone, PartialEq)]
+pub enum UartResponse {
+    Ok,
+    Error,
+    OverVoltage,
+    UnderVoltage,
+    Reset,
+    Ready,
+    Sleep,
+    Wake,
+}
+
+impl UartResponse {
+    /// Parses a `*`-prefixed line sent by the device in UART mode.
+    pub fn parse(response: &str) -> Result<UartResponse, EzoError> {
+        match response {
+            "*OK" => Ok(UartResponse::Ok),
+            "*ER" => Ok(UartResponse::Error),
+            "*OV" => Ok(UartResponse::OverVoltage),
+            "*UV" => Ok(UartResponse::UnderVoltage),
+            "*RS" => Ok(UartResponse::Reset),
+            "*RE" => Ok(UartResponse::Ready),
+            "*SL" => Ok(UartResponse::Sleep),
+            "*WA" => Ok(UartResponse::Wake),
+            _ => Err(ErrorKind::ResponseParse.into()),
+        }
+    }
+}
+
+impl fmt::Debug for UartResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UartResponse::Ok => write!(f, "*OK"),
+            UartResponse::Error => write!(f, "*ER"),
+            UartResponse::OverVoltage => write!(f, "*OV"),
+            UartResponse::UnderVoltage => write!(f, "*UV"),
+            UartResponse::Reset => write!(f, "*RS"),
+            UartResponse::Ready => write!(f, "*RE"),
+            UartResponse::Sleep => write!(f, "*SL"),
+            UartResponse::Wake => write!(f, "*WA"),
+        }
+    }
+}
+
+impl fmt::Display for UartResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UartResponse::Ok => write!(f, "ok"),
+            UartResponse::Error => write!(f, "error"),
+            UartResponse::OverVoltage => write!(f, "over-voltage"),
+            UartResponse::UnderVoltage => write!(f, "under-voltage"),
+            UartResponse::Reset => write!(f, "reset"),
+            UartResponse::Ready => write!(f, "ready"),
+            UartResponse::Sleep => write!(f, "sleep"),
+            UartResponse::Wake => write!(f, "wake"),
+        }
+    }
+}
+
+/// Name stored on the device.
+#[derive(Clone, PartialEq)]
+pub struct DeviceName(pub String);
+
+impl DeviceName {
+    /// Parses the result of the "Name,?" command to get the device's name.
+    pub fn parse(response: &str) -> Result<DeviceName, EzoError> {
+        if response.to_uppercase().starts_with("?NAME,") {
+            let name = response.get(6..).unwrap();
+            if is_valid_name(name) {
+                Ok(DeviceName(name.to_string()))
+            } else {
+                Err(ErrorKind::ResponseParse.into())
+            }
+        } else {
+            Err(ErrorKind::ResponseParse.into())
+        }
+    }
+}
+
+impl fmt::Debug for DeviceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "?Name,{}", self.0)
+    }
+}
+
+impl fmt::Display for DeviceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Any response from the PH EZO chip.
+#[derive(Clone, PartialEq)]
+pub enum PhResponse {
+    Ack,
+    NoResponse,
+    CalibrationStatus(CalibrationStatus),
+    SensorReading(SensorReading),
+    ProbeSlope(ProbeSlope),
+    CompensationValue(CompensationValue),
+    PhScale(PhScale),
+    DeviceName(DeviceName),
+    DeviceInfo(DeviceInfo),
+    DeviceStatus(DeviceStatus),
+    Exported(Exported),
+    ExportedInfo(ExportedInfo),
+    LedStatus(LedStatus),
+    ProtocolLockStatus(ProtocolLockStatus),
+}
+
+impl PhResponse {
+    /// Parses any response from the device, according to its prefix.
+    ///
+    /// Empty responses and `*OK` are acknowledgements, and responses that
+    /// are numbers are readings, within the limits of the extended pH
+    /// scale, since the device may be using either scale. Exported
+    /// calibration data has no prefix, and is only parsed by
+    /// `parse_export`; other responses fail with `ErrorKind::ResponseParse`.
+    pub fn parse(response: &str) -> Result<PhResponse, EzoError> {
+        let prefix = response.split(',').next().unwrap().to_uppercase();
+        let resp = match prefix.as_ref() {
+            "" | "*OK" => PhResponse::Ack,
+            "*ER" => return Err(ErrorKind::DeviceErrorResponse.into()),
+            "?CAL" => PhResponse::CalibrationStatus(CalibrationStatus::parse(response)?),
+            "?SLOPE" => PhResponse::ProbeSlope(ProbeSlope::parse(response)?),
+            "?T" => PhResponse::CompensationValue(CompensationValue::parse(response)?),
+            "?PHEXT" => PhResponse::PhScale(PhScale::parse(response)?),
+            "?NAME" => PhResponse::DeviceName(DeviceName::parse(response)?),
+            "?I" => PhResponse::DeviceInfo(DeviceInfo::parse(response)?),
+            "?STATUS" => PhResponse::DeviceStatus(DeviceStatus::parse(response)?),
+            "?EXPORT" => PhResponse::ExportedInfo(ExportedInfo::parse(response)?),
+            "?L" => PhResponse::LedStatus(LedStatus::parse(response)?),
+            "?PLOCK" => PhResponse::ProtocolLockStatus(ProtocolLockStatus::parse(response)?),
+            p if p.starts_with('?') || p.starts_with('*') => {
+                let msg = format!("unknown response prefix: {:?}", p);
+                return Err(err_msg(msg).context(ErrorKind::ResponseParse).into());
+            }
+            _ => match f64::from_str(response) {
+                Ok(_) => PhResponse::SensorReading(SensorReading::parse_with_scale(
+                    response,
+                    PhScale::Extended,
+                )?),
+                Err(_) => {
+                    let msg = format!("unknown response: {:?}", response);
+                    return Err(err_msg(msg).context(ErrorKind::ResponseParse).into());
+                }
+            },
+        };
+        Ok(resp)
+    }
+
+    /// Parses a response to `EXPORT`: a string of calibration data, or
+    /// `*DONE` once all of it was sent.
+    pub fn parse_export(response: &str) -> Result<PhResponse, EzoError> {
+        Ok(PhResponse::Exported(Exported::parse(response)?))
+    }
+}
+
+impl fmt::Debug for PhResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PhResponse::Ack => write!(f, "*OK"),
+            PhResponse::NoResponse => write!(f, ""),
+            PhResponse::CalibrationStatus(ref r) => write!(f, "{:?}", r),
+            PhResponse::SensorReading(ref r) => write!(f, "{:?}", r),
+            PhResponse::ProbeSlope(ref r) => write!(f, "{:?}", r),
+            PhResponse::CompensationValue(ref r) => write!(f, "{:?}", r),
+            PhResponse::PhScale(ref r) => write!(f, "{:?}", r),
+            PhResponse::DeviceName(ref r) => write!(f, "{:?}", r),
+            PhResponse::DeviceInfo(ref r) => write!(f, "{:?}", r),
+            PhResponse::DeviceStatus(ref r) => write!(f, "{:?}", r),
+            PhResponse::Exported(ref r) => write!(f, "{:?}", r),
+            PhResponse::ExportedInfo(ref r) => write!(f, "{:?}", r),
+            PhResponse::LedStatus(ref r) => write!(f, "{:?}", r),
+            PhResponse::ProtocolLockStatus(ref r) => write!(f, "{:?}", r),
+        }
+    }
+}
+
+impl fmt::Display for PhResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PhResponse::Ack => write!(f, "ok"),
+            PhResponse::NoResponse => write!(f, "none"),
+            PhResponse::CalibrationStatus(ref r) => write!(f, "{}", r),
+            PhResponse::SensorReading(ref r) => write!(f, "{}", r),
+            PhResponse::ProbeSlope(ref r) => write!(f, "{}", r),
+            PhResponse::CompensationValue(ref r) => write!(f, "{}", r),
+            PhResponse::PhScale(ref r) => write!(f, "{}", r),
+            PhResponse::DeviceName(ref r) => write!(f, "{}", r),
+            PhResponse::DeviceInfo(ref r) => write!(f, "{}", r),
+            PhResponse::DeviceStatus(ref r) => write!(f, "{}", r),
+            PhResponse::Exported(ref r) => write!(f, "{}", r),
+            PhResponse::ExportedInfo(ref r) => write!(f, "{}", r),
+            PhResponse::LedStatus(ref r) => write!(f, "{}", r),
+            PhResponse::ProtocolLockStatus(ref r) => write!(f, "{}", r),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use failure::Fail;
 
     #[test]
     fn parses_calibration_status() {
@@ -277,6 +572,63 @@ mod tests {
         assert!(SensorReading::parse(response).is_err());
     }
 
+    #[test]
+    fn parses_sensor_reading_with_scale() {
+        let response = "-1.6";
+        assert_eq!(
+            SensorReading::parse_with_scale(response, PhScale::Extended).unwrap(),
+            SensorReading(-1.6)
+        );
+
+        let response = "15.6";
+        assert_eq!(
+            SensorReading::parse_with_scale(response, PhScale::Extended).unwrap(),
+            SensorReading(15.6)
+        );
+
+        let response = "14.0";
+        assert_eq!(
+            SensorReading::parse_with_scale(response, PhScale::Standard).unwrap(),
+            SensorReading(14.0)
+        );
+    }
+
+    #[test]
+    fn parsing_invalid_sensor_reading_with_scale_yields_error() {
+        let response = "-0.5";
+        assert!(SensorReading::parse_with_scale(response, PhScale::Standard).is_err());
+
+        let response = "-1.7";
+        assert!(SensorReading::parse_with_scale(response, PhScale::Extended).is_err());
+
+        let response = "15.7";
+        assert!(SensorReading::parse_with_scale(response, PhScale::Extended).is_err());
+    }
+
+    #[test]
+    fn parses_ph_scale() {
+        let response = "?pHext,1";
+        assert_eq!(PhScale::parse(response).unwrap(), PhScale::Extended);
+
+        let response = "?PHEXT,0";
+        assert_eq!(PhScale::parse(response).unwrap(), PhScale::Standard);
+    }
+
+    #[test]
+    fn parsing_invalid_ph_scale_yields_error() {
+        let response = "";
+        assert!(PhScale::parse(response).is_err());
+
+        let response = "?pHext,";
+        assert!(PhScale::parse(response).is_err());
+
+        let response = "?pHext,2";
+        assert!(PhScale::parse(response).is_err());
+
+        let response = "?pHext,1,";
+        assert!(PhScale::parse(response).is_err());
+    }
+
     #[test]
     fn parses_probe_slope() {
         let response = "?SLOPE,99.7,-100.3";
@@ -284,7 +636,8 @@ mod tests {
             ProbeSlope::parse(response).unwrap(),
             ProbeSlope {
                 acid_end: 99.7,
-                base_end: -100.3
+                base_end: -100.3,
+                zero_offset_mv: None,
             }
         );
 
@@ -293,7 +646,21 @@ mod tests {
             ProbeSlope::parse(response).unwrap(),
             ProbeSlope {
                 acid_end: 97.0,
-                base_end: -99.3
+                base_end: -99.3,
+                zero_offset_mv: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_probe_slope_with_zero_offset() {
+        let response = "?SLOPE,99.7,100.3,-0.89";
+        assert_eq!(
+            ProbeSlope::parse(response).unwrap(),
+            ProbeSlope {
+                acid_end: 99.7,
+                base_end: 100.3,
+                zero_offset_mv: Some(-0.89),
             }
         );
     }
@@ -311,6 +678,23 @@ mod tests {
 
         let response = "?SLOPE,1,foo,";
         assert!(ProbeSlope::parse(response).is_err());
+
+        let response = "?SLOPE,1,2,";
+        assert!(ProbeSlope::parse(response).is_err());
+
+        let response = "?SLOPE,1,2,3,4";
+        assert!(ProbeSlope::parse(response).is_err());
+    }
+
+    #[test]
+    fn formats_probe_slope() {
+        let slope = ProbeSlope::parse("?SLOPE,99.7,100.3").unwrap();
+        assert_eq!(format!("{:?}", slope), "?SLOPE,99.700,100.300");
+        assert_eq!(format!("{}", slope), "99.700,100.300");
+
+        let slope = ProbeSlope::parse("?SLOPE,99.7,100.3,-0.89").unwrap();
+        assert_eq!(format!("{:?}", slope), "?SLOPE,99.700,100.300,-0.890");
+        assert_eq!(format!("{}", slope), "99.700,100.300,-0.890");
     }
 
     #[test]
@@ -336,4 +720,163 @@ mod tests {
         let response = "?T,1.2,43";
         assert!(CompensationValue::parse(response).is_err());
     }
+
+    #[test]
+    fn parses_uart_response() {
+        assert_eq!(UartResponse::parse("*OK").unwrap(), UartResponse::Ok);
+        assert_eq!(UartResponse::parse("*ER").unwrap(), UartResponse::Error);
+        assert_eq!(UartResponse::parse("*OV").unwrap(), UartResponse::OverVoltage);
+        assert_eq!(UartResponse::parse("*UV").unwrap(), UartResponse::UnderVoltage);
+        assert_eq!(UartResponse::parse("*RS").unwrap(), UartResponse::Reset);
+        assert_eq!(UartResponse::parse("*RE").unwrap(), UartResponse::Ready);
+        assert_eq!(UartResponse::parse("*SL").unwrap(), UartResponse::Sleep);
+        assert_eq!(UartResponse::parse("*WA").unwrap(), UartResponse::Wake);
+    }
+
+    #[test]
+    fn parsing_invalid_uart_response_yields_error() {
+        assert!(UartResponse::parse("").is_err());
+        assert!(UartResponse::parse("*").is_err());
+        assert!(UartResponse::parse("*ok").is_err());
+        assert!(UartResponse::parse("7.00").is_err());
+    }
+
+    #[test]
+    fn parses_device_name() {
+        let response = "?Name,Tank-1";
+        assert_eq!(
+            DeviceName::parse(response).unwrap(),
+            DeviceName("Tank-1".to_string())
+        );
+
+        let response = "?NAME,";
+        assert_eq!(
+            DeviceName::parse(response).unwrap(),
+            DeviceName("".to_string())
+        );
+    }
+
+    #[test]
+    fn parsing_invalid_device_name_yields_error() {
+        let response = "";
+        assert!(DeviceName::parse(response).is_err());
+
+        let response = "?Name";
+        assert!(DeviceName::parse(response).is_err());
+
+        let response = "?Name,a,b";
+        assert!(DeviceName::parse(response).is_err());
+
+        let response = "?Name,12345678901234567";
+        assert!(DeviceName::parse(response).is_err());
+    }
+
+    #[test]
+    fn parses_ph_response() {
+        let response = "?CAL,2";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::CalibrationStatus(CalibrationStatus::TwoPoint)
+        );
+
+        let response = "?SLOPE,99.7,100.3,-0.89";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::ProbeSlope(ProbeSlope::parse(response).unwrap())
+        );
+
+        let response = "?T,19.5";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::CompensationValue(CompensationValue(19.5))
+        );
+
+        let response = "?pHext,1";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::PhScale(PhScale::Extended)
+        );
+
+        let response = "?Name,Tank-1";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::DeviceName(DeviceName("Tank-1".to_string()))
+        );
+
+        let response = "?I,pH,2.10";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::DeviceInfo(DeviceInfo::parse(response).unwrap())
+        );
+
+        let response = "?STATUS,P,5.038";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::DeviceStatus(DeviceStatus::parse(response).unwrap())
+        );
+
+        let response = "?L,1";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::LedStatus(LedStatus::On)
+        );
+
+        let response = "?PLOCK,0";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::ProtocolLockStatus(ProtocolLockStatus::Off)
+        );
+
+        let response = "7.012";
+        assert_eq!(
+            PhResponse::parse(response).unwrap(),
+            PhResponse::SensorReading(SensorReading(7.012))
+        );
+
+        let response = "";
+        assert_eq!(PhResponse::parse(response).unwrap(), PhResponse::Ack);
+
+        let response = "*OK";
+        assert_eq!(PhResponse::parse(response).unwrap(), PhResponse::Ack);
+
+        let response = "*DONE";
+        assert_eq!(
+            PhResponse::parse_export(response).unwrap(),
+            PhResponse::Exported(Exported::Done)
+        );
+
+        let response = "a0b1c2";
+        assert_eq!(
+            PhResponse::parse_export(response).unwrap(),
+            PhResponse::Exported(Exported::ExportString(response.to_string()))
+        );
+    }
+
+    #[test]
+    fn parsing_invalid_ph_response_yields_error() {
+        let err = PhResponse::parse("?FOO,1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseParse);
+        let cause = err.cause().unwrap().to_string();
+        assert!(cause.contains("?FOO"));
+
+        let err = PhResponse::parse("*ER").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+
+        assert!(PhResponse::parse("*WA").is_err());
+        assert!(PhResponse::parse("?CAL,5").is_err());
+        assert_eq!(
+            PhResponse::parse("14.5").unwrap(),
+            PhResponse::SensorReading(SensorReading(14.5))
+        );
+        assert_eq!(
+            PhResponse::parse("-1.2").unwrap(),
+            PhResponse::SensorReading(SensorReading(-1.2))
+        );
+        let err = PhResponse::parse("16.5").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidReading);
+
+        let err = PhResponse::parse("hello").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseParse);
+        assert!(PhResponse::parse("*DONE").is_err());
+    }
 }
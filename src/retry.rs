@@ -0,0 +1,300 @@
+//! Retrying of commands that fail with transient errors.
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::command::TransportCommand;
+use super::transport::EzoTransport;
+use super::{ErrorKind, EzoError};
+
+use failure::Fail;
+
+/// When, and how often, to retry a command that failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry, in milliseconds.
+    pub backoff: u64,
+    /// Factor applied to the wait before each following retry.
+    pub backoff_factor: u64,
+    /// Kinds of errors that are worth retrying.
+    pub retryable: Vec<ErrorKind>,
+    /// Overall time allowed for all the attempts, in milliseconds. With
+    /// `run_on`, it is the time waited through the transport, for the
+    /// commands and between attempts.
+    pub timeout: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts within five seconds, retrying I2C failures and
+    /// responses that are pending or have no data.
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: 100,
+            backoff_factor: 2,
+            retryable: vec![
+                ErrorKind::I2CRead,
+                ErrorKind::UnwritableCommand,
+                ErrorKind::PendingResponse,
+                ErrorKind::NoDataExpectedResponse,
+            ],
+            timeout: Some(5_000),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Whether errors of this kind are worth retrying.
+    pub fn is_retryable(&self, kind: ErrorKind) -> bool {
+        self.retryable.contains(&kind)
+    }
+
+    /// Calls `f`, such as a closure around `Command::run`, until it
+    /// succeeds or the policy gives up. Sleeps between attempts.
+    pub fn run<R, F>(&self, mut f: F) -> Result<R, RetryError>
+    where
+        F: FnMut() -> Result<R, EzoError>,
+    {
+        let start = Instant::now();
+        self.retry(
+            &mut (),
+            |_| f(),
+            |_, millis| thread::sleep(Duration::from_millis(millis)),
+            |_| start.elapsed().as_millis() as u64,
+        )
+    }
+
+    /// Runs `cmd` on `transport` until it succeeds or the policy gives up.
+    /// Waits between attempts through the transport.
+    pub fn run_on<C, T>(&self, cmd: &C, transport: &mut T) -> Result<C::Response, RetryError>
+    where
+        C: TransportCommand,
+        T: EzoTransport,
+    {
+        let mut timed = TimedTransport::new(transport);
+        self.retry(
+            &mut timed,
+            |t| cmd.run_on(t),
+            |t, millis| t.wait(millis),
+            |t| t.waited(),
+        )
+    }
+
+    /// Calls `attempt` until it succeeds or the policy gives up, calling
+    /// `wait` between attempts. `elapsed` tells the time spent so far.
+    fn retry<C, R, F, W, E>(
+        &self,
+        ctx: &mut C,
+        mut attempt: F,
+        mut wait: W,
+        elapsed: E,
+    ) -> Result<R, RetryError>
+    where
+        F: FnMut(&mut C) -> Result<R, EzoError>,
+        W: FnMut(&mut C, u64),
+        E: Fn(&C) -> u64,
+    {
+        let mut backoff = self.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match attempt(ctx) {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+            let timed_out = match self.timeout {
+                Some(timeout) => elapsed(ctx) + backoff >= timeout,
+                None => false,
+            };
+            if attempts >= self.max_attempts || timed_out || !self.is_retryable(error.kind()) {
+                return Err(RetryError { attempts, error });
+            }
+            wait(ctx, backoff);
+            backoff = backoff.saturating_mul(self.backoff_factor);
+        }
+    }
+}
+
+/// A transport that adds up the time waited through it, so that time is
+/// measured the same way on a device and on a simulated one.
+pub struct TimedTransport<'a, T: EzoTransport + 'a> {
+    transport: &'a mut T,
+    waited: u64,
+}
+
+impl<'a, T: EzoTransport> TimedTransport<'a, T> {
+    /// Wraps `transport`, with no time waited yet.
+    pub fn new(transport: &'a mut T) -> TimedTransport<'a, T> {
+        TimedTransport {
+            transport,
+            waited: 0,
+        }
+    }
+
+    /// Milliseconds waited through this transport.
+    pub fn waited(&self) -> u64 {
+        self.waited
+    }
+}
+
+impl<'a, T: EzoTransport> EzoTransport for TimedTransport<'a, T> {
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError> {
+        self.transport.write_command(cmd)
+    }
+
+    fn wait(&mut self, millis: u64) {
+        self.waited = self.waited.saturating_add(millis);
+        self.transport.wait(millis);
+    }
+
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        self.transport.read_response(buf)
+    }
+}
+
+/// Error returned when a `RetryPolicy` gives up.
+#[derive(Debug)]
+pub struct RetryError {
+    /// Number of attempts that were made.
+    pub attempts: u32,
+    /// Error of the last attempt.
+    pub error: EzoError,
+}
+
+impl RetryError {
+    /// Kind of the last attempt's error.
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl fmt::Display for RetryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gave up after {} attempts: {}",
+            self.attempts, self.error
+        )
+    }
+}
+
+impl Fail for RetryError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{Command, Reading, TemperatureCompensation};
+    use mock::{MockDevice, NO_DATA, SYNTAX_ERROR};
+    use response::SensorReading;
+
+    #[test]
+    fn retries_transient_errors() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(NO_DATA)
+            .queue_code(NO_DATA)
+            .queue_data("7.012");
+
+        let reading = RetryPolicy::default().run_on(&Reading, &mut dev).unwrap();
+        assert_eq!(reading, SensorReading(7.012));
+        assert_eq!(dev.commands(), vec!["R", "R", "R"]);
+        assert_eq!(dev.waits(), vec![900, 100, 900, 200, 900]);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut dev = MockDevice::new();
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            ..RetryPolicy::default()
+        };
+
+        let err = policy.run_on(&Reading, &mut dev).unwrap_err();
+        assert_eq!(err.attempts, 2);
+        assert_eq!(err.kind(), ErrorKind::NoDataExpectedResponse);
+        assert_eq!(
+            err.to_string(),
+            "gave up after 2 attempts: the device has no data to respond"
+        );
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(SYNTAX_ERROR).queue_ack();
+
+        let err = RetryPolicy::default()
+            .run_on(&TemperatureCompensation(25.0), &mut dev)
+            .unwrap_err();
+        assert_eq!(err.attempts, 1);
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+        assert_eq!(dev.pending_responses(), 1);
+    }
+
+    #[test]
+    fn gives_up_on_timeout() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            backoff: 100,
+            timeout: Some(250),
+            ..RetryPolicy::default()
+        };
+
+        let mut calls = 0;
+        let err = policy
+            .run(|| -> Result<(), EzoError> {
+                calls += 1;
+                Err(ErrorKind::I2CRead)?
+            })
+            .unwrap_err();
+        assert_eq!(err.attempts, 2);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn times_out_on_the_time_waited_through_the_transport() {
+        let mut dev = MockDevice::new();
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            backoff: 100,
+            backoff_factor: 1,
+            timeout: Some(2_500),
+            ..RetryPolicy::default()
+        };
+
+        let err = policy.run_on(&Reading, &mut dev).unwrap_err();
+        assert_eq!(err.attempts, 3);
+        assert_eq!(dev.waits(), vec![900, 100, 900, 100, 900]);
+    }
+
+    #[test]
+    fn wraps_any_command_run() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(NO_DATA).queue_data("7.012");
+
+        let reading = RetryPolicy {
+            backoff: 1,
+            ..RetryPolicy::default()
+        }
+        .run(|| {
+            let cmd = Reading;
+            assert_eq!(cmd.get_command_string(), "R");
+            cmd.run_on(&mut dev)
+        })
+        .unwrap();
+        assert_eq!(reading, SensorReading(7.012));
+    }
+}
@@ -0,0 +1,684 @@
+//! High-level driver for the PH EZO Chip.
+use std::slice;
+use std::time::Duration;
+
+use super::calibration::CalibrationPoint;
+use super::command::{
+    CalibrationClear, CalibrationHigh, CalibrationLow, CalibrationMid, CalibrationState,
+    CompensatedTemperatureValue, DeviceInformation, ExtendedScaleOff, ExtendedScaleOn,
+    ExtendedScaleState, Import, PhCommand, ScaledReading, Sleep, Slope, Status,
+    TemperatureCompensation, TransportCommand,
+};
+use super::health::{HealthThresholds, ProbeHealth};
+use super::history::{CalibrationEntry, HistoryError, HistoryStore};
+use super::response::{
+    CalibrationStatus, CompensationValue, DeviceInfo, DeviceStatus, PhResponse, PhScale,
+    ProbeSlope, SensorReading,
+};
+use super::retry::TimedTransport;
+use super::stability::{Stability, StabilityDetector, StabilityError, StableReading};
+use super::transport::{EzoTransport, Polling};
+use super::{ErrorKind, EzoError};
+
+use chrono::Utc;
+
+use failure::ResultExt;
+
+use i2cdev::linux::LinuxI2CDevice;
+
+/// Default I2C address of the PH EZO Chip.
+pub const DEFAULT_ADDRESS: u16 = 0x63;
+
+/// A PH EZO Chip, reached through a transport at a given address.
+///
+/// The last known temperature compensation, calibration status and pH
+/// scale are cached, so they can be checked without talking to the device.
+/// Readings are checked against the limits of the last known scale, or of
+/// the extended scale, which includes the standard one, until it is known.
+///
+/// Commands wait for their fixed delay, unless a `Polling` mode is set.
+///
+/// When a `HistoryStore` is set, every calibration and import made through
+/// the methods below is recorded in it. Commands sent with `run`, or with
+/// `Command::run` and `TransportCommand::run_on`, are not recorded.
+#[derive(Debug)]
+pub struct PhSensor<T: EzoTransport> {
+    transport: T,
+    address: u16,
+    polling: Option<Polling>,
+    temperature: Option<f64>,
+    calibration: Option<CalibrationStatus>,
+    scale: Option<PhScale>,
+    history: Option<Box<dyn HistoryStore>>,
+    history_error: Option<HistoryError>,
+}
+
+impl PhSensor<LinuxI2CDevice> {
+    /// Opens the sensor at `address` on the I2C bus `/dev/i2c-<bus>`.
+    /// Fails with `ErrorKind::I2CRead` if the bus cannot be opened.
+    pub fn open(bus: u8, address: u16) -> Result<PhSensor<LinuxI2CDevice>, EzoError> {
+        let device_path = format!("/dev/i2c-{}", bus);
+        let dev = LinuxI2CDevice::new(&device_path, address).context(ErrorKind::I2CRead)?;
+        Ok(PhSensor::new(dev, address))
+    }
+}
+
+impl<T: EzoTransport> PhSensor<T> {
+    /// Uses a transport that is already connected to the device.
+    pub fn new(transport: T, address: u16) -> PhSensor<T> {
+        PhSensor {
+            transport,
+            address,
+            polling: None,
+            temperature: None,
+            calibration: None,
+            scale: None,
+            history: None,
+            history_error: None,
+        }
+    }
+
+    /// Address of the device.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The transport used to talk to the device.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// The transport used to talk to the device, mutably.
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Consumes the sensor, returning its transport.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Polls for responses instead of waiting for fixed delays, or goes
+    /// back to fixed delays with `None`.
+    pub fn set_polling(&mut self, polling: Option<Polling>) {
+        self.polling = polling;
+    }
+
+    /// Polling mode, if set.
+    pub fn polling(&self) -> Option<Polling> {
+        self.polling
+    }
+
+    /// Records every calibration in `history`, or stops recording with
+    /// `None`.
+    pub fn set_history(&mut self, history: Option<Box<dyn HistoryStore>>) {
+        self.history = history;
+    }
+
+    /// Where calibrations are recorded, if set.
+    pub fn history(&self) -> Option<&dyn HistoryStore> {
+        self.history.as_ref().map(|history| history.as_ref())
+    }
+
+    /// Returns, and forgets, why the last calibration could not be
+    /// recorded in the history. The calibration itself was made.
+    pub fn take_history_error(&mut self) -> Option<HistoryError> {
+        self.history_error.take()
+    }
+
+    /// Last known temperature compensation, in °C.
+    pub fn last_temperature(&self) -> Option<f64> {
+        self.temperature
+    }
+
+    /// Last known calibration status.
+    pub fn last_calibration(&self) -> Option<CalibrationStatus> {
+        self.calibration
+    }
+
+    /// Last known pH scale.
+    pub fn last_scale(&self) -> Option<PhScale> {
+        self.scale
+    }
+
+    /// Runs any command on the device, as it is. Calibrations run this
+    /// way, like those run with `Command::run`, are not recorded in the
+    /// history; use `execute` or the methods below for that.
+    pub fn run<C: TransportCommand>(&mut self, cmd: &C) -> Result<C::Response, EzoError> {
+        run_with(cmd, &mut self.transport, self.polling.as_ref())
+    }
+
+    /// Takes a pH reading.
+    pub fn read(&mut self) -> Result<SensorReading, EzoError> {
+        let cmd = self.reading();
+        self.run(&cmd)
+    }
+
+    /// The `R` command, for the last known scale.
+    fn reading(&self) -> ScaledReading {
+        ScaledReading(self.scale.unwrap_or(PhScale::Extended))
+    }
+
+    /// Takes readings until they are stable, returning the last one.
+    /// Fails if they have not settled once `stability.timeout` has passed.
+    /// Time is what was waited through the transport for the readings, so
+    /// a simulator gives up after as many readings as a device would.
+    pub fn read_stable(&mut self, stability: &Stability) -> Result<StableReading, StabilityError> {
+        let timeout = Duration::from_millis(stability.timeout);
+        let cmd = self.reading();
+        let mut detector = StabilityDetector::new(*stability);
+        let mut transport = TimedTransport::new(&mut self.transport);
+        let mut readings = 0;
+        loop {
+            let reading = run_with(&cmd, &mut transport, self.polling.as_ref())?;
+            readings += 1;
+            let elapsed = Duration::from_millis(transport.waited());
+            if detector.push(reading) {
+                return Ok(StableReading {
+                    reading,
+                    readings,
+                    elapsed,
+                });
+            }
+            if elapsed >= timeout {
+                return Err(StabilityError::Unsettled { readings, elapsed });
+            }
+        }
+    }
+
+    /// Sets the temperature compensation, in °C.
+    pub fn set_temperature(&mut self, temperature: f64) -> Result<(), EzoError> {
+        self.run(&TemperatureCompensation(temperature))?;
+        self.temperature = Some(temperature);
+        Ok(())
+    }
+
+    /// Queries the temperature compensation.
+    pub fn temperature(&mut self) -> Result<CompensationValue, EzoError> {
+        let value = self.run(&CompensatedTemperatureValue)?;
+        self.temperature = Some(value.0);
+        Ok(value)
+    }
+
+    /// Switches the device to the given pH scale.
+    pub fn set_scale(&mut self, scale: PhScale) -> Result<(), EzoError> {
+        match scale {
+            PhScale::Standard => self.run(&ExtendedScaleOff)?,
+            PhScale::Extended => self.run(&ExtendedScaleOn)?,
+        };
+        self.scale = Some(scale);
+        Ok(())
+    }
+
+    /// Queries the pH scale.
+    pub fn scale(&mut self) -> Result<PhScale, EzoError> {
+        let scale = self.run(&ExtendedScaleState)?;
+        self.scale = Some(scale);
+        Ok(scale)
+    }
+
+    /// Runs any command on the device. Calibrations go through the
+    /// methods below, so that they are recorded, and readings, the
+    /// temperature compensation and the pH scale through the methods
+    /// above, so that they are checked and cached.
+    pub fn execute(&mut self, cmd: &PhCommand) -> Result<PhResponse, EzoError> {
+        match *cmd {
+            PhCommand::CalibrationMid(CalibrationMid(ph)) => self.calibrate_mid(ph)?,
+            PhCommand::CalibrationLow(CalibrationLow(ph)) => self.calibrate_low(ph)?,
+            PhCommand::CalibrationHigh(CalibrationHigh(ph)) => self.calibrate_high(ph)?,
+            PhCommand::CalibrationClear(_) => self.clear_calibration()?,
+            PhCommand::Import(Import(ref string)) => {
+                self.import_calibration(slice::from_ref(string))?
+            }
+            PhCommand::TemperatureCompensation(TemperatureCompensation(t)) => {
+                self.set_temperature(t)?
+            }
+            PhCommand::Reading(_) => return Ok(PhResponse::SensorReading(self.read()?)),
+            PhCommand::ExtendedScaleOn(_) => self.set_scale(PhScale::Extended)?,
+            PhCommand::ExtendedScaleOff(_) => self.set_scale(PhScale::Standard)?,
+            PhCommand::ExtendedScaleState(_) => return Ok(PhResponse::PhScale(self.scale()?)),
+            _ => return self.run(cmd),
+        }
+        Ok(PhResponse::Ack)
+    }
+
+    /// Calibrates the mid point, which clears the low and high points.
+    pub fn calibrate_mid(&mut self, ph: f64) -> Result<(), EzoError> {
+        self.calibrate(
+            |sensor| sensor.run(&CalibrationMid(ph)).map(|_| ()),
+            Some(CalibrationPoint::Mid),
+            Some(ph),
+            false,
+            Some(CalibrationStatus::OnePoint),
+        )
+    }
+
+    /// Calibrates the low point.
+    pub fn calibrate_low(&mut self, ph: f64) -> Result<(), EzoError> {
+        self.calibrate(
+            |sensor| sensor.run(&CalibrationLow(ph)).map(|_| ()),
+            Some(CalibrationPoint::Low),
+            Some(ph),
+            false,
+            None,
+        )
+    }
+
+    /// Calibrates the high point.
+    pub fn calibrate_high(&mut self, ph: f64) -> Result<(), EzoError> {
+        self.calibrate(
+            |sensor| sensor.run(&CalibrationHigh(ph)).map(|_| ()),
+            Some(CalibrationPoint::High),
+            Some(ph),
+            false,
+            None,
+        )
+    }
+
+    /// Clears the calibration.
+    pub fn clear_calibration(&mut self) -> Result<(), EzoError> {
+        self.calibrate(
+            |sensor| sensor.run(&CalibrationClear).map(|_| ()),
+            None,
+            None,
+            false,
+            Some(CalibrationStatus::NotCalibrated),
+        )
+    }
+
+    /// Imports calibration strings, as sent by `EXPORT`, recording them as
+    /// a single entry.
+    pub fn import_calibration(&mut self, data: &[String]) -> Result<(), EzoError> {
+        self.calibrate(
+            |sensor| {
+                for string in data {
+                    sensor.run(&Import(string.clone()))?;
+                }
+                Ok(())
+            },
+            None,
+            None,
+            true,
+            None,
+        )
+    }
+
+    /// Calibrates with `calibrate`, or imports a calibration, after which
+    /// the calibration status is `status`, if known. If a history is set, the slope and temperature
+    /// are queried first, and nothing is calibrated if that fails. The
+    /// slope and calibration status are queried after calibrating, to
+    /// record an entry; failing to record it does not fail the
+    /// calibration, and is kept for `take_history_error`.
+    fn calibrate<F>(
+        &mut self,
+        calibrate: F,
+        point: Option<CalibrationPoint>,
+        ph: Option<f64>,
+        imported: bool,
+        status: Option<CalibrationStatus>,
+    ) -> Result<(), EzoError>
+    where
+        F: FnOnce(&mut PhSensor<T>) -> Result<(), EzoError>,
+    {
+        self.history_error = None;
+        let before = if self.history.is_some() {
+            Some(self.slope_and_temperature()?)
+        } else {
+            None
+        };
+        self.calibration = None;
+        calibrate(self)?;
+        self.calibration = status;
+        if let Some(before) = before {
+            self.history_error = self.record(point, ph, imported, before).err();
+        }
+        Ok(())
+    }
+
+    /// Queries the probe slope and the temperature compensation, in °C.
+    fn slope_and_temperature(&mut self) -> Result<(ProbeSlope, f64), EzoError> {
+        let slope = self.slope()?;
+        Ok((slope, self.temperature()?.0))
+    }
+
+    /// Records a calibration in the history, from the slope and
+    /// temperature queried before it.
+    fn record(
+        &mut self,
+        point: Option<CalibrationPoint>,
+        ph: Option<f64>,
+        imported: bool,
+        before: (ProbeSlope, f64),
+    ) -> Result<(), HistoryError> {
+        let (slope_before, temperature) = before;
+        let entry = CalibrationEntry {
+            timestamp: Utc::now(),
+            point,
+            ph,
+            imported,
+            temperature,
+            slope_before,
+            slope_after: self.slope()?,
+            status: self.calibration_status()?,
+        };
+        match self.history {
+            Some(ref mut history) => history.append(&entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Queries the calibration status.
+    pub fn calibration_status(&mut self) -> Result<CalibrationStatus, EzoError> {
+        let status = self.run(&CalibrationState)?;
+        self.calibration = Some(status);
+        Ok(status)
+    }
+
+    /// Queries the probe slope.
+    pub fn slope(&mut self) -> Result<ProbeSlope, EzoError> {
+        self.run(&Slope)
+    }
+
+    /// Assesses the probe's health from its slope.
+    pub fn probe_health(&mut self, thresholds: &HealthThresholds) -> Result<ProbeHealth, EzoError> {
+        let slope = self.slope()?;
+        Ok(ProbeHealth::assess(&slope, thresholds))
+    }
+
+    /// Queries the device information.
+    pub fn info(&mut self) -> Result<DeviceInfo, EzoError> {
+        self.run(&DeviceInformation)
+    }
+
+    /// Queries the device status.
+    pub fn status(&mut self) -> Result<DeviceStatus, EzoError> {
+        self.run(&Status)
+    }
+
+    /// Puts the device to sleep. Any following command wakes it up.
+    pub fn sleep(&mut self) -> Result<(), EzoError> {
+        self.run(&Sleep)
+    }
+}
+
+/// Runs `cmd` on `transport`, polling for the response if `polling` is set.
+fn run_with<C, T>(
+    cmd: &C,
+    transport: &mut T,
+    polling: Option<&Polling>,
+) -> Result<C::Response, EzoError>
+where
+    C: TransportCommand,
+    T: EzoTransport,
+{
+    match polling {
+        Some(polling) => cmd.run_polling(transport, polling),
+        None => cmd.run_on(transport),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use health::ProbeCondition;
+    use history::MemoryHistory;
+    use mock::MockDevice;
+    use simulator::{ProbeModel, Simulator};
+
+    use std::io;
+
+    #[test]
+    fn reads_and_caches_temperature() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(sensor.last_temperature(), None);
+
+        sensor.set_temperature(21.5).unwrap();
+        assert_eq!(sensor.last_temperature(), Some(21.5));
+        assert_eq!(sensor.temperature().unwrap(), CompensationValue(21.5));
+
+        sensor.transport_mut().set_solution_ph(6.5);
+        let reading = sensor.read().unwrap();
+        assert!((reading.0 - 6.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn caches_calibration_status() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(sensor.last_calibration(), None);
+
+        sensor.calibrate_mid(7.0).unwrap();
+        assert_eq!(sensor.last_calibration(), Some(CalibrationStatus::OnePoint));
+
+        sensor.transport_mut().set_solution_ph(4.0);
+        sensor.calibrate_low(4.0).unwrap();
+        assert_eq!(sensor.last_calibration(), None);
+        assert_eq!(
+            sensor.calibration_status().unwrap(),
+            CalibrationStatus::TwoPoint
+        );
+        assert_eq!(sensor.last_calibration(), Some(CalibrationStatus::TwoPoint));
+
+        sensor.clear_calibration().unwrap();
+        assert_eq!(
+            sensor.last_calibration(),
+            Some(CalibrationStatus::NotCalibrated)
+        );
+    }
+
+    #[test]
+    fn failed_command_does_not_update_cache() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(2);
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        assert!(sensor.set_temperature(25.0).is_err());
+        assert_eq!(sensor.last_temperature(), None);
+        assert_eq!(sensor.transport().commands(), vec!["T,25.000"]);
+    }
+
+    #[test]
+    fn records_calibrations_in_history() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.calibrate_mid(7.0).unwrap();
+        assert!(sensor.history().is_none());
+
+        sensor.set_history(Some(Box::new(MemoryHistory::new())));
+        sensor.set_temperature(22.0).unwrap();
+        sensor.transport_mut().probe_mut().acid_slope = 95.0;
+        sensor.transport_mut().set_solution_ph(4.0);
+        sensor.calibrate_low(4.0).unwrap();
+        sensor.clear_calibration().unwrap();
+
+        let entries = sensor.history().unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].point, Some(CalibrationPoint::Low));
+        assert_eq!(entries[0].ph, Some(4.0));
+        assert_eq!(entries[0].temperature, 22.0);
+        assert_eq!(entries[0].slope_before.acid_end, 100.0);
+        assert!(entries[0].slope_after.acid_end < 100.0);
+        assert_eq!(entries[0].status, CalibrationStatus::TwoPoint);
+        assert_eq!(entries[1].point, None);
+        assert_eq!(entries[1].status, CalibrationStatus::NotCalibrated);
+    }
+
+    /// A history that cannot be written to.
+    #[derive(Debug)]
+    struct ReadOnlyHistory;
+
+    impl HistoryStore for ReadOnlyHistory {
+        fn append(&mut self, _entry: &CalibrationEntry) -> Result<(), HistoryError> {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only").into())
+        }
+
+        fn entries(&self) -> Result<Vec<CalibrationEntry>, HistoryError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn history_failures_do_not_hide_the_calibration() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.set_history(Some(Box::new(ReadOnlyHistory)));
+        sensor.calibrate_mid(7.0).unwrap();
+        assert_eq!(sensor.last_calibration(), Some(CalibrationStatus::OnePoint));
+        match sensor.take_history_error() {
+            Some(HistoryError::Io(_)) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(sensor.take_history_error().is_none());
+
+        let mut dev = MockDevice::new();
+        dev.queue_data("?SLOPE,100.0,100.0")
+            .queue_data("?T,25.000")
+            .queue_ack()
+            .queue_code(2);
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        sensor.set_history(Some(Box::new(MemoryHistory::new())));
+        sensor.calibrate_mid(7.0).unwrap();
+        match sensor.take_history_error() {
+            Some(HistoryError::Device(_)) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(sensor.history().unwrap().entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn does_not_calibrate_when_the_state_before_is_unknown() {
+        let mut dev = MockDevice::new();
+        dev.queue_code(2);
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        sensor.set_history(Some(Box::new(MemoryHistory::new())));
+        let err = sensor.calibrate_mid(7.0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+        assert_eq!(sensor.transport().commands(), vec!["SLOPE,?"]);
+        assert_eq!(sensor.last_calibration(), None);
+        assert!(sensor.take_history_error().is_none());
+
+        let mut dev = MockDevice::new();
+        dev.queue_code(2);
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        let err = sensor.calibrate_low(4.0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+    }
+
+    #[test]
+    fn records_imports() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.set_history(Some(Box::new(MemoryHistory::new())));
+        let data = vec!["MP7.000".to_string(), "MV0.0000".to_string()];
+        sensor.import_calibration(&data).unwrap();
+        let cmd = "Import,MT25.000".parse::<PhCommand>().unwrap();
+        assert_eq!(sensor.execute(&cmd).unwrap(), PhResponse::Ack);
+
+        let entries = sensor.history().unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].imported);
+        assert_eq!(entries[0].point, None);
+        assert_eq!(entries[0].status, CalibrationStatus::OnePoint);
+        assert_eq!(sensor.transport().calibration_points(), 1);
+    }
+
+    #[test]
+    fn executes_calibrations_through_the_history() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.set_history(Some(Box::new(MemoryHistory::new())));
+        let cmd = "Cal,mid,7.00".parse::<PhCommand>().unwrap();
+        assert_eq!(sensor.execute(&cmd).unwrap(), PhResponse::Ack);
+        let cmd = "T,21.5".parse::<PhCommand>().unwrap();
+        sensor.execute(&cmd).unwrap();
+        assert_eq!(sensor.last_temperature(), Some(21.5));
+        let cmd = "Cal,?".parse::<PhCommand>().unwrap();
+        sensor.execute(&cmd).unwrap();
+        assert_eq!(sensor.history().unwrap().entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reads_on_the_last_known_scale() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(sensor.last_scale(), None);
+        sensor.set_scale(PhScale::Extended).unwrap();
+        assert_eq!(sensor.last_scale(), Some(PhScale::Extended));
+        sensor.transport_mut().set_solution_ph(14.8);
+        let reading = sensor.read().unwrap();
+        assert!((reading.0 - 14.8).abs() < 0.05);
+        let stable = sensor.read_stable(&Stability::default()).unwrap();
+        assert!((stable.reading.0 - 14.8).abs() < 0.05);
+        let cmd = "pHext,?".parse::<PhCommand>().unwrap();
+        assert_eq!(
+            sensor.execute(&cmd).unwrap(),
+            PhResponse::PhScale(PhScale::Extended)
+        );
+
+        let mut dev = MockDevice::new();
+        dev.queue_data("14.500")
+            .queue_data("?pHext,0")
+            .queue_data("14.500");
+        let mut sensor = PhSensor::new(dev, DEFAULT_ADDRESS);
+        assert_eq!(sensor.read().unwrap(), SensorReading(14.5));
+        assert_eq!(sensor.scale().unwrap(), PhScale::Standard);
+        let err = sensor.read().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidReading);
+    }
+
+    #[test]
+    fn polls_for_responses() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        sensor.set_polling(Some(Polling {
+            interval: 100,
+            deadline: 2_000,
+        }));
+        sensor.read().unwrap();
+        assert_eq!(sensor.transport().elapsed(), 900);
+        sensor.set_temperature(20.0).unwrap();
+        assert_eq!(sensor.transport().elapsed(), 1_200);
+
+        let mut sensor = PhSensor::new(MockDevice::new(), DEFAULT_ADDRESS);
+        sensor.transport_mut().queue_code(254).queue_data("7.000");
+        sensor.set_polling(Some(Polling::default()));
+        sensor.read().unwrap();
+        assert_eq!(sensor.transport().waits(), vec![50, 50]);
+    }
+
+    #[test]
+    fn reads_until_stable() {
+        let probe = ProbeModel {
+            noise_mv: 0.5,
+            ..ProbeModel::default()
+        };
+        let mut sensor = PhSensor::new(Simulator::new(probe), DEFAULT_ADDRESS);
+        sensor.transport_mut().set_solution_ph(4.0);
+        let stable = sensor.read_stable(&Stability::default()).unwrap();
+        assert_eq!(stable.readings, 5);
+        assert!((stable.reading.0 - 4.0).abs() < 0.01);
+
+        let probe = ProbeModel {
+            drift_mv: 2.0,
+            ..ProbeModel::default()
+        };
+        let mut sensor = PhSensor::new(Simulator::new(probe), DEFAULT_ADDRESS);
+        let stability = Stability {
+            timeout: 9_000,
+            ..Stability::default()
+        };
+        match sensor.read_stable(&stability).unwrap_err() {
+            StabilityError::Unsettled { readings, elapsed } => {
+                assert_eq!(readings, 10);
+                assert_eq!(elapsed, Duration::from_millis(9_000));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(sensor.transport().elapsed(), 9_000);
+    }
+
+    #[test]
+    fn device_queries() {
+        let mut sensor = PhSensor::new(Simulator::default(), DEFAULT_ADDRESS);
+        assert_eq!(sensor.address(), 0x63);
+        assert_eq!(sensor.info().unwrap().device, "pH");
+        assert_eq!(sensor.status().unwrap().vcc_voltage, 5.0);
+        assert_eq!(sensor.slope().unwrap().acid_end, 100.0);
+        let health = sensor.probe_health(&HealthThresholds::default()).unwrap();
+        assert_eq!(health.condition, ProbeCondition::Good);
+        sensor.sleep().unwrap();
+        assert!(sensor.into_inner().is_asleep());
+    }
+}
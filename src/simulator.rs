@@ -0,0 +1,714 @@
+//! Software emulation of the PH EZO Chip, with a model of the pH probe.
+//!
+//! The simulator keeps the chip's state (calibration points, temperature
+//! compensation, LED, protocol lock, sleep), and derives readings from a
+//! `ProbeModel` immersed in a solution of known pH. Calibration commands
+//! change what `R` and `SLOPE,?` return, just like on the real chip.
+//! `FACTORY` clears the calibration, name and LED state, and `I2C` and
+//! `BAUD` are refused while the protocol is locked.
+//!
+//! `EXPORT` sends the calibration points as short strings, in a format of
+//! the simulator's own, which `IMPORT` understands.
+use std::str::FromStr;
+
+use super::command::{
+    CalibrationHigh, CalibrationLow, CalibrationMid, Name, PhCommand, ReadingWithTemperature,
+    TemperatureCompensation,
+};
+use super::mock::{NO_DATA, PENDING, SUCCESS, SYNTAX_ERROR};
+use super::response::PhScale;
+use super::transport::EzoTransport;
+use super::{ErrorKind, EzoError};
+
+/// Ideal probe slope at 0 °C, in mV per pH unit per kelvin.
+const NERNST_FACTOR: f64 = 0.198_416;
+
+/// Absolute zero, in °C.
+const ZERO_CELSIUS_IN_KELVIN: f64 = 273.15;
+
+/// Ideal probe slope, in mV per pH unit, at the given temperature in °C.
+pub fn nernst_slope(temperature: f64) -> f64 {
+    NERNST_FACTOR * (temperature + ZERO_CELSIUS_IN_KELVIN)
+}
+
+/// Electrical behaviour of a pH probe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeModel {
+    /// Probe potential at pH 7, in mV.
+    pub offset_mv: f64,
+    /// Slope below pH 7, as a percentage of the ideal slope.
+    pub acid_slope: f64,
+    /// Slope above pH 7, as a percentage of the ideal slope.
+    pub base_slope: f64,
+    /// Maximum random deviation added to each reading, in mV.
+    pub noise_mv: f64,
+    /// Potential added to the offset after each reading, in mV.
+    pub drift_mv: f64,
+    /// Temperature of the solution, in °C.
+    pub temperature: f64,
+}
+
+impl Default for ProbeModel {
+    /// An ideal probe at 25 °C.
+    fn default() -> ProbeModel {
+        ProbeModel {
+            offset_mv: 0.0,
+            acid_slope: 100.0,
+            base_slope: 100.0,
+            noise_mv: 0.0,
+            drift_mv: 0.0,
+            temperature: 25.0,
+        }
+    }
+}
+
+impl ProbeModel {
+    /// Potential of the probe, in mV, when immersed in a solution of the
+    /// given pH, excluding noise.
+    pub fn potential(&self, ph: f64) -> f64 {
+        let slope = if ph < 7.0 {
+            self.acid_slope
+        } else {
+            self.base_slope
+        };
+        self.offset_mv - (slope / 100.0) * nernst_slope(self.temperature) * (ph - 7.0)
+    }
+}
+
+/// A calibration point, as stored by the chip.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CalibrationPoint {
+    ph: f64,
+    millivolts: f64,
+    temperature: f64,
+}
+
+/// Emulation of the PH EZO Chip, usable as an `EzoTransport`.
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    probe: ProbeModel,
+    solution_ph: f64,
+    compensation: f64,
+    mid: Option<CalibrationPoint>,
+    low: Option<CalibrationPoint>,
+    high: Option<CalibrationPoint>,
+    scale: PhScale,
+    name: String,
+    led: bool,
+    protocol_lock: bool,
+    asleep: bool,
+    vcc_voltage: f64,
+    noise_state: u32,
+    exported: usize,
+    response: Option<(u8, String)>,
+    processing: u64,
+    elapsed: u64,
+}
+
+impl Simulator {
+    /// Creates an uncalibrated chip with the given probe, immersed in a
+    /// solution of pH 7.
+    pub fn new(probe: ProbeModel) -> Simulator {
+        Simulator {
+            probe,
+            solution_ph: 7.0,
+            compensation: 25.0,
+            mid: None,
+            low: None,
+            high: None,
+            scale: PhScale::Standard,
+            name: String::new(),
+            led: true,
+            protocol_lock: false,
+            asleep: false,
+            vcc_voltage: 5.0,
+            noise_state: 0x2545_f491,
+            exported: 0,
+            response: None,
+            processing: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Simulated time waited so far, in milliseconds.
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// The probe model.
+    pub fn probe(&self) -> &ProbeModel {
+        &self.probe
+    }
+
+    /// The probe model, for changing its behaviour.
+    pub fn probe_mut(&mut self) -> &mut ProbeModel {
+        &mut self.probe
+    }
+
+    /// Immerses the probe in a solution of the given pH.
+    pub fn set_solution_ph(&mut self, ph: f64) {
+        self.solution_ph = ph;
+    }
+
+    /// pH of the solution the probe is immersed in.
+    pub fn solution_ph(&self) -> f64 {
+        self.solution_ph
+    }
+
+    /// Temperature, in °C, currently used for compensation.
+    pub fn compensation(&self) -> f64 {
+        self.compensation
+    }
+
+    /// Whether the chip is in sleep mode.
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Number of calibration points stored by the chip.
+    pub fn calibration_points(&self) -> u8 {
+        [self.mid, self.low, self.high]
+            .iter()
+            .filter(|p| p.is_some())
+            .count() as u8
+    }
+
+    /// Uniformly distributed noise in `[-1, 1]`, from a xorshift generator.
+    fn next_noise(&mut self) -> f64 {
+        let mut x = self.noise_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_state = x;
+        (f64::from(x) / f64::from(u32::MAX)) * 2.0 - 1.0
+    }
+
+    /// Measures the probe potential, applying noise and drift.
+    fn measure(&mut self) -> f64 {
+        let noise = self.next_noise() * self.probe.noise_mv;
+        let millivolts = self.probe.potential(self.solution_ph) + noise;
+        self.probe.offset_mv += self.probe.drift_mv;
+        millivolts
+    }
+
+    /// Ratio of the measured slope to the ideal slope, on one side of the
+    /// mid point.
+    fn slope_ratio(&self, point: Option<CalibrationPoint>) -> f64 {
+        match (self.mid, point) {
+            (Some(mid), Some(point)) => {
+                let measured = (point.millivolts - mid.millivolts) / (mid.ph - point.ph);
+                measured / nernst_slope(point.temperature)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Probe potential at pH 7, in mV, implied by the mid point calibration.
+    fn zero_offset(&self) -> f64 {
+        match self.mid {
+            Some(mid) => mid.millivolts + (mid.ph - 7.0) * nernst_slope(mid.temperature),
+            None => 0.0,
+        }
+    }
+
+    /// Converts a probe potential to pH, using the chip's calibration.
+    fn to_ph(&self, millivolts: f64) -> f64 {
+        let (mid_ph, mid_mv) = match self.mid {
+            Some(mid) => (mid.ph, mid.millivolts),
+            None => (7.0, 0.0),
+        };
+        let ratio = if millivolts > mid_mv {
+            self.slope_ratio(self.low)
+        } else {
+            self.slope_ratio(self.high)
+        };
+        mid_ph - (millivolts - mid_mv) / (ratio * nernst_slope(self.compensation))
+    }
+
+    /// Takes a reading of the solution, as the `R` command does.
+    pub fn reading(&mut self) -> f64 {
+        let millivolts = self.measure();
+        let ph = self.to_ph(millivolts);
+        ph.max(self.scale.lower_limit())
+            .min(self.scale.upper_limit())
+    }
+
+    fn calibration_point(&mut self, ph: f64) -> CalibrationPoint {
+        CalibrationPoint {
+            ph,
+            millivolts: self.measure(),
+            temperature: self.compensation,
+        }
+    }
+
+    /// The calibration points, as sent by `EXPORT`. Each point is sent as
+    /// its pH, potential and temperature, and a missing point as `-`.
+    fn export_strings(&self) -> Vec<String> {
+        let mut strings = Vec::new();
+        for &(tag, point) in &[('M', self.mid), ('L', self.low), ('H', self.high)] {
+            match point {
+                Some(point) => {
+                    strings.push(format!("{}P{:.3}", tag, point.ph));
+                    strings.push(format!("{}V{:.4}", tag, point.millivolts));
+                    strings.push(format!("{}T{:.3}", tag, point.temperature));
+                }
+                None => strings.push(format!("{}P-", tag)),
+            }
+        }
+        strings
+    }
+
+    /// Imports a string sent by `EXPORT`, returning whether it is valid.
+    fn import_string(&mut self, string: &str) -> bool {
+        let mut chars = string.chars();
+        let (tag, field) = match (chars.next(), chars.next()) {
+            (Some(tag), Some(field)) => (tag, field),
+            _ => return false,
+        };
+        let value = chars.as_str();
+        let slot = match tag {
+            'M' => &mut self.mid,
+            'L' => &mut self.low,
+            'H' => &mut self.high,
+            _ => return false,
+        };
+        if field == 'P' && value == "-" {
+            *slot = None;
+            return true;
+        }
+        let value = match f64::from_str(value) {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        let point = slot.get_or_insert(CalibrationPoint {
+            ph: 7.0,
+            millivolts: 0.0,
+            temperature: 25.0,
+        });
+        match field {
+            'P' => point.ph = value,
+            'V' => point.millivolts = value,
+            'T' => point.temperature = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Processes a command string, returning the response code, the
+    /// response data, and the processing time in milliseconds. Commands
+    /// that get no response return `None`.
+    fn process(&mut self, cmd: &str) -> Option<(u8, String, u64)> {
+        self.asleep = false;
+        let supper = cmd.to_uppercase();
+        let ack = |delay| Some((SUCCESS, String::new(), delay));
+        let data = |data: String, delay| Some((SUCCESS, data, delay));
+        match supper.as_ref() {
+            "R" => {
+                let ph = self.reading();
+                data(format!("{:.*}", 3, ph), 900)
+            }
+            "CAL,?" => data(format!("?CAL,{}", self.calibration_points()), 300),
+            "CAL,CLEAR" => {
+                self.mid = None;
+                self.low = None;
+                self.high = None;
+                ack(300)
+            }
+            "SLOPE,?" => {
+                let acid = self.slope_ratio(self.low) * 100.0;
+                let base = self.slope_ratio(self.high) * 100.0;
+                let offset = self.zero_offset();
+                data(
+                    format!("?SLOPE,{:.*},{:.*},{:.*}", 1, acid, 1, base, 2, offset),
+                    300,
+                )
+            }
+            "T,?" => data(format!("?T,{:.*}", 3, self.compensation), 300),
+            "I" => data("?I,pH,2.10".to_string(), 300),
+            "STATUS" => data(format!("?STATUS,P,{:.*}", 3, self.vcc_voltage), 300),
+            "L,1" => {
+                self.led = true;
+                ack(300)
+            }
+            "L,0" => {
+                self.led = false;
+                ack(300)
+            }
+            "L,?" => data(format!("?L,{}", self.led as u8), 300),
+            "PLOCK,1" => {
+                self.protocol_lock = true;
+                ack(300)
+            }
+            "PLOCK,0" => {
+                self.protocol_lock = false;
+                ack(300)
+            }
+            "PHEXT,1" => {
+                self.scale = PhScale::Extended;
+                ack(300)
+            }
+            "PHEXT,0" => {
+                self.scale = PhScale::Standard;
+                ack(300)
+            }
+            "PHEXT,?" => data(format!("{:?}", self.scale), 300),
+            "NAME,?" => data(format!("?Name,{}", self.name), 300),
+            "PLOCK,?" => data(format!("?PLOCK,{}", self.protocol_lock as u8), 300),
+            "F" => ack(300),
+            "EXPORT,?" => {
+                let strings = self.export_strings();
+                let bytes: usize = strings.iter().map(|s| s.len()).sum();
+                self.exported = 0;
+                data(format!("?EXPORT,{},{}", strings.len(), bytes), 300)
+            }
+            "EXPORT" => {
+                let strings = self.export_strings();
+                match strings.get(self.exported) {
+                    Some(string) => {
+                        self.exported += 1;
+                        data(string.clone(), 300)
+                    }
+                    None => {
+                        self.exported = 0;
+                        data("*DONE".to_string(), 300)
+                    }
+                }
+            }
+            "SLEEP" => {
+                self.asleep = true;
+                None
+            }
+            "FACTORY" => {
+                self.mid = None;
+                self.low = None;
+                self.high = None;
+                self.name = String::new();
+                self.led = true;
+                None
+            }
+            _ => self.process_with_value(cmd),
+        }
+    }
+
+    /// Processes commands that carry a value.
+    fn process_with_value(&mut self, cmd: &str) -> Option<(u8, String, u64)> {
+        if let Ok(CalibrationMid(ph)) = CalibrationMid::from_str(cmd) {
+            // Calibrating the mid point clears the other points.
+            self.mid = Some(self.calibration_point(ph));
+            self.low = None;
+            self.high = None;
+        } else if let Ok(CalibrationLow(ph)) = CalibrationLow::from_str(cmd) {
+            self.low = Some(self.calibration_point(ph));
+        } else if let Ok(CalibrationHigh(ph)) = CalibrationHigh::from_str(cmd) {
+            self.high = Some(self.calibration_point(ph));
+        } else if let Ok(TemperatureCompensation(t)) = TemperatureCompensation::from_str(cmd) {
+            self.compensation = t;
+            return Some((SUCCESS, String::new(), 300));
+        } else if cmd.to_uppercase().starts_with("IMPORT,") {
+            if self.import_string(&cmd[7..]) {
+                return Some((SUCCESS, String::new(), 300));
+            }
+            return Some((SYNTAX_ERROR, String::new(), 300));
+        } else if let Ok(name) = Name::from_str(cmd) {
+            self.name = name.name().to_string();
+            return Some((SUCCESS, String::new(), 300));
+        } else if let Ok(ReadingWithTemperature(t)) = ReadingWithTemperature::from_str(cmd) {
+            self.compensation = t;
+            let ph = self.reading();
+            return Some((SUCCESS, format!("{:.*}", 3, ph), 900));
+        } else {
+            return match PhCommand::from_str(cmd) {
+                // The chip reboots with the new address, or in UART mode,
+                // unless the protocol is locked.
+                Ok(PhCommand::DeviceAddress(_)) | Ok(PhCommand::Baud(_)) if !self.protocol_lock => {
+                    None
+                }
+                _ => Some((SYNTAX_ERROR, String::new(), 300)),
+            };
+        }
+        Some((SUCCESS, String::new(), 900))
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Simulator {
+        Simulator::new(ProbeModel::default())
+    }
+}
+
+impl EzoTransport for Simulator {
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError> {
+        match self.process(cmd) {
+            Some((code, data, delay)) => {
+                self.response = Some((code, data));
+                self.processing = delay;
+            }
+            None => {
+                self.response = None;
+                self.processing = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Advances the simulated time, without sleeping.
+    fn wait(&mut self, millis: u64) {
+        self.processing = self.processing.saturating_sub(millis);
+        self.elapsed = self.elapsed.saturating_add(millis);
+    }
+
+    /// Replies with `PENDING` until the command's processing time has been
+    /// waited for.
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        let (code, data) = match self.response.take() {
+            Some(response) => {
+                if self.processing > 0 {
+                    self.response = Some(response);
+                    (PENDING, String::new())
+                } else {
+                    response
+                }
+            }
+            None => (NO_DATA, String::new()),
+        };
+        let bytes = data.as_bytes();
+        if bytes.len() + 2 > buf.len() {
+            return Err(ErrorKind::MalformedResponse)?;
+        }
+        buf[0] = code;
+        buf[1..=bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len() + 1] = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{
+        CalibrationClear, CalibrationState, CompensatedTemperatureValue, DeviceInformation,
+        ExtendedScaleOn, ExtendedScaleState, Factory, LedOff, LedState, NameState,
+        ProtocolLockDisable, ProtocolLockEnable, ProtocolLockState, Reading, ScaledReading, Sleep,
+        Slope, Status, TransportCommand,
+    };
+    use response::{CalibrationStatus, LedStatus, ProtocolLockStatus};
+    use transport::Polling;
+
+    fn biased_probe() -> ProbeModel {
+        ProbeModel {
+            offset_mv: 20.0,
+            acid_slope: 97.0,
+            base_slope: 95.0,
+            ..ProbeModel::default()
+        }
+    }
+
+    #[test]
+    fn ideal_probe_reads_solution_ph() {
+        let mut sim = Simulator::default();
+        sim.set_solution_ph(4.0);
+        let reading = Reading.run_on(&mut sim).unwrap();
+        assert!((reading.0 - 4.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn calibration_corrects_biased_probe() {
+        let mut sim = Simulator::new(biased_probe());
+        sim.set_solution_ph(4.0);
+        let reading = Reading.run_on(&mut sim).unwrap();
+        assert!((reading.0 - 4.0).abs() > 0.2);
+
+        sim.set_solution_ph(7.0);
+        CalibrationMid(7.0).run_on(&mut sim).unwrap();
+        sim.set_solution_ph(4.0);
+        CalibrationLow(4.0).run_on(&mut sim).unwrap();
+        sim.set_solution_ph(10.0);
+        CalibrationHigh(10.0).run_on(&mut sim).unwrap();
+
+        let status = CalibrationState.run_on(&mut sim).unwrap();
+        assert_eq!(status, CalibrationStatus::ThreePoint);
+
+        for &ph in &[4.0, 5.5, 7.0, 8.5, 10.0] {
+            sim.set_solution_ph(ph);
+            let reading = Reading.run_on(&mut sim).unwrap();
+            assert!((reading.0 - ph).abs() < 0.001);
+        }
+
+        let slope = Slope.run_on(&mut sim).unwrap();
+        assert_eq!(slope.acid_end, 97.0);
+        assert_eq!(slope.base_end, 95.0);
+        assert_eq!(slope.zero_offset_mv, Some(20.0));
+    }
+
+    #[test]
+    fn mid_calibration_clears_other_points() {
+        let mut sim = Simulator::new(biased_probe());
+        CalibrationMid(7.0).run_on(&mut sim).unwrap();
+        sim.set_solution_ph(4.0);
+        CalibrationLow(4.0).run_on(&mut sim).unwrap();
+        assert_eq!(sim.calibration_points(), 2);
+
+        sim.set_solution_ph(7.0);
+        CalibrationMid(7.0).run_on(&mut sim).unwrap();
+        assert_eq!(sim.calibration_points(), 1);
+
+        CalibrationClear.run_on(&mut sim).unwrap();
+        let status = CalibrationState.run_on(&mut sim).unwrap();
+        assert_eq!(status, CalibrationStatus::NotCalibrated);
+    }
+
+    #[test]
+    fn temperature_compensation() {
+        let mut sim = Simulator::default();
+        TemperatureCompensation(19.5).run_on(&mut sim).unwrap();
+        let value = CompensatedTemperatureValue.run_on(&mut sim).unwrap();
+        assert_eq!(value.0, 19.5);
+
+        // The probe is at 25 °C, but the chip compensates for 19.5 °C.
+        sim.set_solution_ph(4.0);
+        let reading = Reading.run_on(&mut sim).unwrap();
+        assert!(reading.0 < 4.0);
+
+        let reading = ReadingWithTemperature(25.0).run_on(&mut sim).unwrap();
+        assert!((reading.0 - 4.0).abs() < 0.001);
+        assert_eq!(sim.compensation(), 25.0);
+    }
+
+    #[test]
+    fn extended_scale_readings() {
+        let mut sim = Simulator::default();
+        sim.set_solution_ph(-1.0);
+        let reading = Reading.run_on(&mut sim).unwrap();
+        assert_eq!(reading.0, 0.0);
+
+        ExtendedScaleOn.run_on(&mut sim).unwrap();
+        assert_eq!(
+            ExtendedScaleState.run_on(&mut sim).unwrap(),
+            PhScale::Extended
+        );
+        let reading = ScaledReading(PhScale::Extended).run_on(&mut sim).unwrap();
+        assert!((reading.0 + 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn noise_stays_within_bounds() {
+        let mut sim = Simulator::new(ProbeModel {
+            noise_mv: 1.0,
+            ..ProbeModel::default()
+        });
+        let max_deviation = 1.0 / nernst_slope(25.0);
+        for _ in 0..100 {
+            let reading = sim.reading();
+            assert!((reading - 7.0).abs() <= max_deviation + 1e-9);
+        }
+    }
+
+    #[test]
+    fn device_commands() {
+        let mut sim = Simulator::default();
+        let info = DeviceInformation.run_on(&mut sim).unwrap();
+        assert_eq!(info.device, "pH");
+
+        let status = Status.run_on(&mut sim).unwrap();
+        assert_eq!(status.vcc_voltage, 5.0);
+
+        Name::new("Tank-1").unwrap().run_on(&mut sim).unwrap();
+        let name = NameState.run_on(&mut sim).unwrap();
+        assert_eq!(name.0, "Tank-1");
+
+        LedOff.run_on(&mut sim).unwrap();
+        assert_eq!(LedState.run_on(&mut sim).unwrap(), LedStatus::Off);
+
+        Sleep.run_on(&mut sim).unwrap();
+        assert!(sim.is_asleep());
+        Reading.run_on(&mut sim).unwrap();
+        assert!(!sim.is_asleep());
+    }
+
+    #[test]
+    fn factory_reset_clears_calibration_name_and_led() {
+        let mut sim = Simulator::default();
+        CalibrationMid(7.0).run_on(&mut sim).unwrap();
+        Name::new("Tank-1").unwrap().run_on(&mut sim).unwrap();
+        LedOff.run_on(&mut sim).unwrap();
+
+        Factory.run_on(&mut sim).unwrap();
+        assert_eq!(sim.calibration_points(), 0);
+        assert_eq!(NameState.run_on(&mut sim).unwrap().0, "");
+        assert_eq!(LedState.run_on(&mut sim).unwrap(), LedStatus::On);
+    }
+
+    #[test]
+    fn protocol_lock_refuses_address_and_baud_changes() {
+        let mut sim = Simulator::default();
+        let mut buf = [0u8; 8];
+        for cmd in &["I2C,100", "BAUD,9600"] {
+            sim.write_command(cmd).unwrap();
+            sim.wait(300);
+            sim.read_response(&mut buf).unwrap();
+            assert_eq!(buf[0], NO_DATA);
+        }
+
+        ProtocolLockEnable.run_on(&mut sim).unwrap();
+        assert_eq!(
+            ProtocolLockState.run_on(&mut sim).unwrap(),
+            ProtocolLockStatus::On
+        );
+        for cmd in &["I2C,100", "BAUD,9600"] {
+            sim.write_command(cmd).unwrap();
+            sim.wait(300);
+            sim.read_response(&mut buf).unwrap();
+            assert_eq!(buf[0], SYNTAX_ERROR);
+        }
+
+        ProtocolLockDisable.run_on(&mut sim).unwrap();
+        sim.write_command("I2C,100").unwrap();
+        sim.read_response(&mut buf).unwrap();
+        assert_eq!(buf[0], NO_DATA);
+    }
+
+    #[test]
+    fn reading_before_processing_is_done_is_pending() {
+        let mut sim = Simulator::default();
+        sim.write_command("R").unwrap();
+        sim.wait(300);
+        let mut buf = [0u8; 8];
+        sim.read_response(&mut buf).unwrap();
+        assert_eq!(buf[0], PENDING);
+        sim.wait(600);
+        sim.read_response(&mut buf).unwrap();
+        assert_eq!(buf[0], SUCCESS);
+    }
+
+    #[test]
+    fn polling_returns_as_soon_as_reading_is_ready() {
+        let mut sim = Simulator::default();
+        let polling = Polling {
+            interval: 100,
+            deadline: 2_000,
+        };
+        Reading.run_polling(&mut sim, &polling).unwrap();
+        assert_eq!(sim.elapsed(), 900);
+        TemperatureCompensation(20.0)
+            .run_polling(&mut sim, &polling)
+            .unwrap();
+        assert_eq!(sim.elapsed(), 1_200);
+
+        let polling = Polling {
+            interval: 0,
+            deadline: 2_000,
+        };
+        Reading.run_polling(&mut sim, &polling).unwrap();
+        assert_eq!(sim.elapsed(), 2_100);
+    }
+
+    #[test]
+    fn unknown_command_is_syntax_error() {
+        let mut sim = Simulator::default();
+        sim.write_command("FOO").unwrap();
+        sim.wait(300);
+        let mut buf = [0u8; 8];
+        sim.read_response(&mut buf).unwrap();
+        assert_eq!(buf[0], SYNTAX_ERROR);
+    }
+}
@@ -0,0 +1,265 @@
+//! Detection of when successive pH readings have settled.
+use std::cmp;
+use std::collections::VecDeque;
+use std::f64;
+use std::fmt;
+use std::time::Duration;
+
+use super::response::SensorReading;
+use super::EzoError;
+
+use failure::Fail;
+
+/// When readings are considered stable: the last `window` readings are
+/// within `max_delta` pH of each other, and their standard deviation is
+/// at most `max_std_dev`. Either bound may be left out. Readings are
+/// taken for at most `timeout` milliseconds. A `window` of 0 is taken as
+/// a single reading.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stability {
+    pub window: usize,
+    pub max_delta: Option<f64>,
+    pub max_std_dev: Option<f64>,
+    pub timeout: u64,
+}
+
+impl Default for Stability {
+    /// Five readings within 0.02 pH, for up to a minute.
+    fn default() -> Stability {
+        Stability {
+            window: 5,
+            max_delta: Some(0.02),
+            max_std_dev: None,
+            timeout: 60_000,
+        }
+    }
+}
+
+/// Keeps the last readings, and tells whether they are stable.
+#[derive(Clone, Debug)]
+pub struct StabilityDetector {
+    stability: Stability,
+    readings: VecDeque<f64>,
+}
+
+impl StabilityDetector {
+    /// Creates a detector with no readings.
+    pub fn new(stability: Stability) -> StabilityDetector {
+        let stability = Stability {
+            window: cmp::max(stability.window, 1),
+            ..stability
+        };
+        StabilityDetector {
+            stability,
+            readings: VecDeque::with_capacity(stability.window),
+        }
+    }
+
+    /// The criteria used by this detector.
+    pub fn stability(&self) -> &Stability {
+        &self.stability
+    }
+
+    /// Adds a reading, dropping the oldest one once the window is full.
+    /// Returns whether the readings are now stable.
+    pub fn push(&mut self, reading: SensorReading) -> bool {
+        if self.readings.len() == self.stability.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(reading.0);
+        self.is_stable()
+    }
+
+    /// Forgets all readings.
+    pub fn reset(&mut self) {
+        self.readings.clear();
+    }
+
+    /// Whether the window is full, and its readings meet the criteria.
+    pub fn is_stable(&self) -> bool {
+        if self.readings.is_empty() || self.readings.len() < self.stability.window {
+            return false;
+        }
+        let delta_ok = match self.stability.max_delta {
+            Some(max) => self.delta() <= max,
+            None => true,
+        };
+        let std_dev_ok = match self.stability.max_std_dev {
+            Some(max) => self.std_dev() <= max,
+            None => true,
+        };
+        delta_ok && std_dev_ok
+    }
+
+    /// The latest reading, if any.
+    pub fn last(&self) -> Option<SensorReading> {
+        self.readings.back().map(|&ph| SensorReading(ph))
+    }
+
+    /// Mean of the readings in the window.
+    pub fn mean(&self) -> f64 {
+        self.readings.iter().sum::<f64>() / self.readings.len() as f64
+    }
+
+    /// Difference between the highest and lowest readings in the window.
+    pub fn delta(&self) -> f64 {
+        let min = self.readings.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = self
+            .readings
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        max - min
+    }
+
+    /// Population standard deviation of the readings in the window.
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        let variance = self
+            .readings
+            .iter()
+            .map(|ph| (ph - mean).powi(2))
+            .sum::<f64>()
+            / self.readings.len() as f64;
+        variance.sqrt()
+    }
+}
+
+/// A reading that has settled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StableReading {
+    /// The last reading taken.
+    pub reading: SensorReading,
+    /// Number of readings taken.
+    pub readings: usize,
+    /// Time it took for the readings to settle.
+    pub elapsed: Duration,
+}
+
+/// Error returned while waiting for readings to settle.
+#[derive(Debug)]
+pub enum StabilityError {
+    /// The readings did not settle before the timeout.
+    Unsettled { readings: usize, elapsed: Duration },
+    /// Communicating with the device failed.
+    Device(EzoError),
+}
+
+impl From<EzoError> for StabilityError {
+    fn from(error: EzoError) -> StabilityError {
+        StabilityError::Device(error)
+    }
+}
+
+impl fmt::Display for StabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StabilityError::Unsettled { readings, elapsed } => write!(
+                f,
+                "readings did not settle after {} readings in {}.{:03} s",
+                readings,
+                elapsed.as_secs(),
+                elapsed.subsec_millis()
+            ),
+            StabilityError::Device(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Fail for StabilityError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            StabilityError::Device(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(stability: Stability, readings: &[f64]) -> StabilityDetector {
+        let mut detector = StabilityDetector::new(stability);
+        for &ph in readings {
+            detector.push(SensorReading(ph));
+        }
+        detector
+    }
+
+    #[test]
+    fn stable_once_window_is_full_and_within_delta() {
+        let stability = Stability {
+            window: 3,
+            ..Stability::default()
+        };
+        let mut detector = StabilityDetector::new(stability);
+        assert!(!detector.push(SensorReading(7.10)));
+        assert!(!detector.push(SensorReading(7.05)));
+        assert!(!detector.push(SensorReading(7.02)));
+        assert!(!detector.push(SensorReading(7.01)));
+        assert!(detector.push(SensorReading(7.015)));
+        assert!((detector.delta() - 0.01).abs() < 1e-9);
+        assert_eq!(detector.last(), Some(SensorReading(7.015)));
+
+        detector.reset();
+        assert!(!detector.is_stable());
+        assert_eq!(detector.last(), None);
+    }
+
+    #[test]
+    fn checks_standard_deviation() {
+        let stability = Stability {
+            window: 4,
+            max_delta: None,
+            max_std_dev: Some(0.01),
+            ..Stability::default()
+        };
+        let stable = detector(stability, &[7.00, 7.02, 7.00, 7.02]);
+        assert!((stable.mean() - 7.01).abs() < 1e-9);
+        assert!((stable.std_dev() - 0.01).abs() < 1e-9);
+        assert!(stable.is_stable());
+
+        let unstable = detector(stability, &[7.00, 7.03, 7.00, 7.03]);
+        assert!(!unstable.is_stable());
+    }
+
+    #[test]
+    fn checks_both_bounds() {
+        let stability = Stability {
+            window: 2,
+            max_delta: Some(0.05),
+            max_std_dev: Some(0.01),
+            ..Stability::default()
+        };
+        assert!(!detector(stability, &[7.00, 7.04]).is_stable());
+        assert!(detector(stability, &[7.00, 7.02]).is_stable());
+    }
+
+    #[test]
+    fn empty_window_holds_one_reading() {
+        let stability = Stability {
+            window: 0,
+            ..Stability::default()
+        };
+        let mut detector = StabilityDetector::new(stability);
+        assert_eq!(detector.stability().window, 1);
+        assert!(!detector.is_stable());
+        assert!(detector.push(SensorReading(7.10)));
+        assert!(detector.push(SensorReading(6.50)));
+        assert_eq!(detector.readings.len(), 1);
+        assert_eq!(detector.last(), Some(SensorReading(6.50)));
+    }
+
+    #[test]
+    fn unsettled_error_message() {
+        let err = StabilityError::Unsettled {
+            readings: 12,
+            elapsed: Duration::from_millis(10_800),
+        };
+        assert_eq!(
+            err.to_string(),
+            "readings did not settle after 12 readings in 10.800 s"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Transports used to talk to the PH EZO Chip.
+//!
+//! Commands are written as ASCII strings, and responses are read back as
+//! a buffer whose first byte is the response code, followed by the
+//! nul-terminated ASCII data. This is the I2C framing, which other
+//! transports translate to and from.
+use std::thread;
+use std::time::Duration;
+
+use super::{ErrorKind, EzoError};
+
+use ezo_common::{response_code, string_from_response_data, write_to_ezo, ResponseCode};
+
+use failure::ResultExt;
+
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CDevice;
+
+/// Busy-polling of the response code, used instead of a command's fixed
+/// delay. The response is read every `interval` milliseconds, for as long
+/// as the device replies that it is still processing the command, and
+/// until `deadline` milliseconds have been waited in total. An `interval`
+/// of 0 is taken as 1 ms.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Polling {
+    pub interval: u64,
+    pub deadline: u64,
+}
+
+impl Default for Polling {
+    fn default() -> Polling {
+        Polling {
+            interval: 50,
+            deadline: 2_000,
+        }
+    }
+}
+
+/// A channel to write commands to, and read responses from, the EZO chip.
+pub trait EzoTransport {
+    /// Writes the ASCII command string to the device.
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError>;
+
+    /// Waits `millis` milliseconds for the device to process a command.
+    fn wait(&mut self, millis: u64) {
+        thread::sleep(Duration::from_millis(millis));
+    }
+
+    /// Reads the response into `buf`, which holds up to `MAX_DATA` bytes.
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError>;
+}
+
+impl EzoTransport for LinuxI2CDevice {
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError> {
+        write_to_ezo(self, cmd)
+    }
+
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        self.read(buf).context(ErrorKind::I2CRead)?;
+        Ok(())
+    }
+}
+
+/// Checks the response code in the first byte of `data`, and returns the
+/// ASCII data that follows it.
+pub fn decode_response(data: &[u8]) -> Result<String, EzoError> {
+    if data.is_empty() {
+        return Err(ErrorKind::MalformedResponse.into());
+    }
+    match response_code(data[0]) {
+        ResponseCode::Success => match data.iter().position(|&c| c == 0) {
+            Some(len) => {
+                let resp = string_from_response_data(&data[1..=len])
+                    .context(ErrorKind::MalformedResponse)?;
+                Ok(resp)
+            }
+            _ => Err(ErrorKind::MalformedResponse)?,
+        },
+        ResponseCode::Pending => Err(ErrorKind::PendingResponse)?,
+        ResponseCode::DeviceError => Err(ErrorKind::DeviceErrorResponse)?,
+        ResponseCode::NoDataExpected => Err(ErrorKind::NoDataExpectedResponse)?,
+        ResponseCode::UnknownError => Err(ErrorKind::MalformedResponse)?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_successful_response() {
+        let data = b"\x017.012\0\0\0";
+        assert_eq!(decode_response(data).unwrap(), "7.012");
+
+        let data = b"\x01\0\0\0";
+        assert_eq!(decode_response(data).unwrap(), "");
+    }
+
+    #[test]
+    fn decoding_error_codes_yields_error() {
+        let data = [2u8, 0];
+        assert_eq!(
+            decode_response(&data).unwrap_err().kind(),
+            ErrorKind::DeviceErrorResponse
+        );
+
+        let data = [254u8, 0];
+        assert_eq!(
+            decode_response(&data).unwrap_err().kind(),
+            ErrorKind::PendingResponse
+        );
+
+        let data = [255u8, 0];
+        assert_eq!(
+            decode_response(&data).unwrap_err().kind(),
+            ErrorKind::NoDataExpectedResponse
+        );
+
+        let data = b"\x017.012";
+        assert!(decode_response(data).is_err());
+
+        assert!(decode_response(&[]).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! UART transport for the PH EZO Chip.
+//!
+//! In UART mode, commands are terminated with `<CR>`, and every command is
+//! answered with optional data lines followed by `*OK` or `*ER`. The chip
+//! may also send `*OV`, `*UV`, `*RS`, `*RE`, `*SL` and `*WA` lines, which
+//! are collected as events.
+//!
+//! Responses are translated into the I2C framing, so they are parsed by
+//! the same response types.
+//!
+//! Whatever the chip sent after the last response, such as the `*OK` and
+//! `*SL` that follow `Sleep`, is discarded before each command, so that it
+//! is not taken for the reply to the command. `UartTransport::open` also
+//! turns off the continuous readings that the chip sends every second by
+//! default in UART mode.
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use super::command::MAX_DATA;
+use super::response::UartResponse;
+use super::transport::{decode_response, EzoTransport};
+use super::{ErrorKind, EzoError};
+
+use failure::{Fail, ResultExt};
+
+use libc;
+use termios::os::target::{B115200, B57600};
+use termios::{
+    cfmakeraw, cfsetspeed, tcflush, tcsetattr, Termios, B1200, B19200, B2400, B300, B38400,
+    B9600, TCIFLUSH, TCIOFLUSH, TCSANOW, VMIN, VTIME,
+};
+
+/// Time to wait for a byte from the serial port, in tenths of a second.
+pub const UART_READ_TIMEOUT: u8 = 10;
+
+/// Maximum length of a single line sent by the device.
+const MAX_LINE: usize = 400;
+
+/// A transport over the chip's UART mode, for any serial port that can be
+/// read from and written to, and whose input can be flushed.
+pub struct UartTransport<P: Read + Write + AsRawFd> {
+    port: P,
+    events: Vec<UartResponse>,
+}
+
+impl UartTransport<File> {
+    /// Opens the serial port at `path`, in raw mode, at the given baud rate,
+    /// and turns off continuous readings.
+    pub fn open<A: AsRef<Path>>(path: A, baud: u32) -> Result<UartTransport<File>, UartError> {
+        let speed = match baud {
+            300 => B300,
+            1200 => B1200,
+            2400 => B2400,
+            9600 => B9600,
+            19200 => B19200,
+            38400 => B38400,
+            57600 => B57600,
+            115200 => B115200,
+            _ => return Err(UartError::UnsupportedBaud(baud)),
+        };
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+        let fd = port.as_raw_fd();
+        let mut termios = Termios::from_fd(fd)?;
+        cfmakeraw(&mut termios);
+        cfsetspeed(&mut termios, speed)?;
+        termios.c_cc[VMIN] = 0;
+        termios.c_cc[VTIME] = UART_READ_TIMEOUT;
+        tcsetattr(fd, TCSANOW, &termios)?;
+        tcflush(fd, TCIOFLUSH)?;
+        let mut uart = UartTransport::new(port);
+        uart.stop_continuous_readings().map_err(UartError::Device)?;
+        Ok(uart)
+    }
+}
+
+impl<P: Read + Write + AsRawFd> UartTransport<P> {
+    /// Uses an already configured serial port.
+    pub fn new(port: P) -> UartTransport<P> {
+        UartTransport {
+            port,
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns, and forgets, the events received since the last call.
+    pub fn take_events(&mut self) -> Vec<UartResponse> {
+        self.events.drain(..).collect()
+    }
+
+    /// Sends `C,0`, so that the chip only sends readings when asked to.
+    /// Readings that were already on their way are discarded.
+    pub fn stop_continuous_readings(&mut self) -> Result<(), EzoError> {
+        self.write_command("C,0")?;
+        let mut data_buffer = [0u8; MAX_DATA];
+        self.read_response(&mut data_buffer)?;
+        decode_response(&data_buffer)?;
+        Ok(())
+    }
+
+    /// Consumes the transport, returning the serial port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Reads a `<CR>`-terminated line, skipping line-feeds. Fails with
+    /// `ErrorKind::PendingResponse` if no byte comes in time, and with
+    /// `ErrorKind::MalformedResponse` if the port cannot be read; the cause
+    /// is a `UartError`.
+    fn read_line(&mut self) -> Result<String, EzoError> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            let n = self
+                .port
+                .read(&mut byte)
+                .map_err(UartError::Io)
+                .context(ErrorKind::MalformedResponse)?;
+            match n {
+                0 => Err(UartError::Timeout).context(ErrorKind::PendingResponse)?,
+                _ => match byte[0] {
+                    b'\r' => break,
+                    b'\n' => continue,
+                    b => line.push(b),
+                },
+            }
+            if line.len() > MAX_LINE {
+                return Err(ErrorKind::MalformedResponse)?;
+            }
+        }
+        let line = String::from_utf8(line).context(ErrorKind::MalformedResponse)?;
+        Ok(line)
+    }
+}
+
+impl<P: Read + Write + AsRawFd> EzoTransport for UartTransport<P> {
+    /// Discards what is left of earlier replies, and writes the command.
+    fn write_command(&mut self, cmd: &str) -> Result<(), EzoError> {
+        tcflush(self.port.as_raw_fd(), TCIFLUSH)
+            .map_err(UartError::Io)
+            .context(ErrorKind::UnwritableCommand)?;
+        let cmd = format!("{}\r", cmd);
+        self.port
+            .write_all(cmd.as_bytes())
+            .and_then(|_| self.port.flush())
+            .map_err(UartError::Io)
+            .context(ErrorKind::UnwritableCommand)?;
+        Ok(())
+    }
+
+    /// Reads lines until `*OK` or `*ER`. The last data line before `*OK`
+    /// becomes the response data. `*`-prefixed lines that are not
+    /// responses, such as the `*DONE` that ends an export, are data.
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        let mut data = String::new();
+        loop {
+            let line = self.read_line()?;
+            let response = match UartResponse::parse(&line) {
+                Ok(response) => response,
+                Err(_) => {
+                    data = line;
+                    continue;
+                }
+            };
+            match response {
+                UartResponse::Ok => {
+                    let bytes = data.as_bytes();
+                    if bytes.len() + 2 > buf.len() {
+                        return Err(ErrorKind::MalformedResponse)?;
+                    }
+                    buf[0] = 1;
+                    buf[1..=bytes.len()].copy_from_slice(bytes);
+                    buf[bytes.len() + 1] = 0;
+                    return Ok(());
+                }
+                UartResponse::Error => {
+                    buf[0] = 2;
+                    return Ok(());
+                }
+                event => self.events.push(event),
+            }
+        }
+    }
+}
+
+/// Errors of the serial port.
+#[derive(Debug)]
+pub enum UartError {
+    /// The chip does not support this baud rate.
+    UnsupportedBaud(u32),
+    /// The serial port could not be set up, read or written.
+    Io(io::Error),
+    /// The chip did not send anything in time.
+    Timeout,
+    /// The chip did not accept `C,0` when the port was opened.
+    Device(EzoError),
+}
+
+impl From<io::Error> for UartError {
+    fn from(error: io::Error) -> UartError {
+        UartError::Io(error)
+    }
+}
+
+impl fmt::Display for UartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UartError::UnsupportedBaud(baud) => write!(f, "unsupported baud rate: {}", baud),
+            UartError::Io(ref e) => write!(f, "serial port error: {}", e),
+            UartError::Timeout => write!(f, "serial port read timed out"),
+            UartError::Device(ref e) => write!(f, "could not turn off continuous readings: {}", e),
+        }
+    }
+}
+
+impl Fail for UartError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match *self {
+            UartError::Io(ref e) => Some(e),
+            UartError::Device(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{
+        CalibrationMid, CalibrationState, Export, ExportInfo, Reading, Sleep, Slope,
+        TransportCommand,
+    };
+    use response::{CalibrationStatus, Exported, ExportedInfo, SensorReading};
+
+    use std::ffi::CStr;
+    use std::os::unix::io::FromRawFd;
+    use std::ptr;
+    use std::thread;
+
+    /// Opens a pseudo-terminal pair, returning the device end, the host
+    /// end, and the path of the host end.
+    fn pty() -> (File, File, String) {
+        let mut master = 0;
+        let mut slave = 0;
+        let mut name = [0 as libc::c_char; 128];
+        let res = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                name.as_mut_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(res, 0);
+        let slave = unsafe { File::from_raw_fd(slave) };
+        let mut termios = Termios::from_fd(slave.as_raw_fd()).unwrap();
+        cfmakeraw(&mut termios);
+        tcsetattr(slave.as_raw_fd(), TCSANOW, &termios).unwrap();
+        let master = unsafe { File::from_raw_fd(master) };
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        (master, slave, name.to_string_lossy().into_owned())
+    }
+
+    /// Opens a pseudo-terminal pair, returning the (device, host) ends.
+    fn pty_pair() -> (File, UartTransport<File>) {
+        let (master, slave, _) = pty();
+        (master, UartTransport::new(slave))
+    }
+
+    /// Reads one `<CR>`-terminated command on the device end, then replies.
+    fn reply(device: &mut File, reply: &str) -> thread::JoinHandle<String> {
+        let mut device = device.try_clone().unwrap();
+        let reply = reply.to_string();
+        thread::spawn(move || {
+            let mut cmd = Vec::new();
+            let mut byte = [0u8; 1];
+            while device.read(&mut byte).unwrap() == 1 && byte[0] != b'\r' {
+                cmd.push(byte[0]);
+            }
+            device.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8(cmd).unwrap()
+        })
+    }
+
+    #[test]
+    fn runs_commands_over_pty() {
+        let (mut device, mut uart) = pty_pair();
+
+        let handle = reply(&mut device, "7.012\r*OK\r");
+        let reading = Reading.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "R");
+        assert_eq!(reading, SensorReading(7.012));
+
+        let handle = reply(&mut device, "*OK\r");
+        CalibrationMid(7.0).run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "CAL,MID,7.00");
+
+        let handle = reply(&mut device, "?CAL,1\r*OK\r");
+        let status = CalibrationState.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "CAL,?");
+        assert_eq!(status, CalibrationStatus::OnePoint);
+
+        let handle = reply(&mut device, "*WA\r?SLOPE,99.7,100.3\r*OK\r");
+        let slope = Slope.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "SLOPE,?");
+        assert_eq!(slope.base_end, 100.3);
+        assert_eq!(uart.take_events(), vec![UartResponse::Wake]);
+        assert!(uart.take_events().is_empty());
+    }
+
+    #[test]
+    fn error_response_over_pty_yields_err() {
+        let (mut device, mut uart) = pty_pair();
+
+        let handle = reply(&mut device, "*ER\r");
+        let err = Reading.run_on(&mut uart).unwrap_err();
+        assert_eq!(handle.join().unwrap(), "R");
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+    }
+
+    #[test]
+    fn leftovers_of_sleep_are_not_taken_for_the_next_reply() {
+        let (mut device, mut uart) = pty_pair();
+
+        let handle = reply(&mut device, "*OK\r*SL\r");
+        Sleep.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "SLEEP");
+        thread::sleep(::std::time::Duration::from_millis(50));
+
+        let handle = reply(&mut device, "*WA\r7.012\r*OK\r");
+        let reading = Reading.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "R");
+        assert_eq!(reading, SensorReading(7.012));
+        assert_eq!(uart.take_events(), vec![UartResponse::Wake]);
+    }
+
+    #[test]
+    fn exports_calibration_over_pty() {
+        let (mut device, mut uart) = pty_pair();
+
+        let handle = reply(&mut device, "?EXPORT,2,24\r*OK\r");
+        let info = ExportInfo.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "EXPORT,?");
+        assert_eq!(
+            info,
+            ExportedInfo {
+                lines: 2,
+                total_bytes: 24
+            }
+        );
+
+        for string in &["596F75206172", "652061207472"] {
+            let handle = reply(&mut device, &format!("{}\r*OK\r", string));
+            let exported = Export.run_on(&mut uart).unwrap();
+            assert_eq!(handle.join().unwrap(), "EXPORT");
+            assert_eq!(exported, Exported::ExportString(string.to_string()));
+        }
+
+        let handle = reply(&mut device, "*DONE\r*OK\r");
+        let exported = Export.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "EXPORT");
+        assert_eq!(exported, Exported::Done);
+        assert!(uart.take_events().is_empty());
+    }
+
+    #[test]
+    fn turns_off_continuous_readings_when_opened() {
+        let (mut device, _host, path) = pty();
+
+        let handle = reply(&mut device, "7.012\r7.013\r*OK\r");
+        let mut uart = UartTransport::open(&path, 9600).unwrap();
+        assert_eq!(handle.join().unwrap(), "C,0");
+
+        let handle = reply(&mut device, "7.014\r*OK\r");
+        let reading = Reading.run_on(&mut uart).unwrap();
+        assert_eq!(handle.join().unwrap(), "R");
+        assert_eq!(reading, SensorReading(7.014));
+    }
+
+    #[test]
+    fn rejects_unsupported_baud_rates() {
+        match UartTransport::open("/dev/null", 4800) {
+            Err(UartError::UnsupportedBaud(4800)) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}